@@ -21,11 +21,102 @@ pub const MAX_DELETE_BATCH_SIZE: usize = 256;
 
 const MAX_DELETE_COUNT_BY_KEY: usize = 2048;
 
+/// Default thresholds for `DeleteStrategy::auto` - see `DeleteStrategy::Auto`.
+pub const AUTO_DELETE_BY_KEY_THRESHOLD: u64 = 32;
+pub const AUTO_DELETE_BY_RANGE_THRESHOLD: u64 = MAX_DELETE_COUNT_BY_KEY as u64;
+
 #[derive(Clone)]
 pub enum DeleteStrategy {
     DeleteByKey,
     DeleteByRange,
-    DeleteByWriter { sst_path: String },
+    DeleteByWriter {
+        sst_path: String,
+    },
+    /// Estimates the number of keys in the range first, then picks
+    /// `DeleteByKey` for a range at or under `small_threshold`,
+    /// `DeleteByRange` at or under `large_threshold`, and `DeleteByWriter`
+    /// above that - the same thresholds every call site used to have to
+    /// hand-pick a strategy around, now centralized in one place. Use
+    /// `DeleteStrategy::auto` to build one with the library's defaults.
+    Auto {
+        sst_path: String,
+        small_threshold: u64,
+        large_threshold: u64,
+    },
+}
+
+impl DeleteStrategy {
+    /// An `Auto` strategy using `AUTO_DELETE_BY_KEY_THRESHOLD` and
+    /// `AUTO_DELETE_BY_RANGE_THRESHOLD` as its crossover points.
+    pub fn auto(sst_path: String) -> DeleteStrategy {
+        DeleteStrategy::Auto {
+            sst_path,
+            small_threshold: AUTO_DELETE_BY_KEY_THRESHOLD,
+            large_threshold: AUTO_DELETE_BY_RANGE_THRESHOLD,
+        }
+    }
+}
+
+/// One SST level's file count and total size, as returned in
+/// [`CfStats::levels`].
+#[derive(Clone, Debug, Default)]
+pub struct LevelStats {
+    pub num_files: u64,
+    pub file_bytes: u64,
+}
+
+/// One column family's memtable, SST, and compaction stats, as returned in
+/// [`EngineStats::cfs`].
+#[derive(Clone, Debug, Default)]
+pub struct CfStats {
+    pub num_memtables: u64,
+    pub memtable_bytes: u64,
+    pub num_immutable_memtables: u64,
+    pub immutable_memtable_bytes: u64,
+    /// Indexed by level, i.e. `levels[0]` is L0.
+    pub levels: Vec<LevelStats>,
+    /// Always zero on a non-Titan engine.
+    pub num_blob_files: u64,
+    pub blob_file_bytes: u64,
+    pub pending_compaction_bytes: u64,
+    pub block_cache_hits: u64,
+    pub block_cache_misses: u64,
+}
+
+/// A structured snapshot of an engine's internal statistics, meant to be
+/// iterated and pushed straight into a metrics registry - one labeled gauge
+/// or counter per field - instead of scraped and regex-parsed out of
+/// [`MiscExt::dump_stats`]'s free-form debugging text.
+#[derive(Clone, Debug, Default)]
+pub struct EngineStats {
+    /// `(cf_name, stats)`, one entry per `CFNamesExt::cf_names`.
+    pub cfs: Vec<(String, CfStats)>,
+    pub num_live_versions: u64,
+    pub num_pending_versions: u64,
+    pub latest_sequence_number: u64,
+    pub oldest_snapshot_sequence_number: Option<u64>,
+}
+
+/// What actually happened to one range passed to
+/// [`MiscExt::delete_all_in_ranges`]. Ranges that got coalesced together
+/// (adjacent or overlapping inputs) report their combined total rather than
+/// one report per input range - see that method.
+#[derive(Clone, Debug, Default)]
+pub struct RangeDeleteReport {
+    /// Exact for `DeleteByKey`/`DeleteByWriter`, which scan the range key by
+    /// key. `DeleteByRange` never scans - it just writes a tombstone - so
+    /// this is `estimate_range_keys_cf`'s estimate instead.
+    pub keys_scanned: u64,
+    /// Same caveat as `keys_scanned`: an estimate, not an exact count, for
+    /// `DeleteByRange`.
+    pub keys_deleted: u64,
+    /// Always zero for `DeleteByRange`: with no scan, there are no key bytes
+    /// to sum.
+    pub bytes_deleted: u64,
+    /// Whether any CF in this range was cleared via `DeleteByWriter`'s
+    /// build-and-ingest path, as opposed to point deletes or
+    /// `delete_range_cf`.
+    pub used_ingest: bool,
 }
 
 pub trait MiscExt: Iterable + WriteBatchExt + CFNamesExt + SstExt + ImportExt {
@@ -166,6 +257,199 @@ pub trait MiscExt: Iterable + WriteBatchExt + CFNamesExt + SstExt + ImportExt {
                     self.write(&wb)?;
                 }
             }
+            DeleteStrategy::Auto {
+                sst_path,
+                small_threshold,
+                large_threshold,
+            } => {
+                let estimated_keys = self.estimate_range_keys_cf(cf, start_key, end_key)?;
+                let picked = if estimated_keys <= small_threshold {
+                    DeleteStrategy::DeleteByKey
+                } else if estimated_keys <= large_threshold {
+                    DeleteStrategy::DeleteByRange
+                } else {
+                    DeleteStrategy::DeleteByWriter { sst_path }
+                };
+                return self.delete_all_in_range_cf(cf, picked, start_key, end_key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimates the number of live keys in `[start_key, end_key)` of `cf`,
+    /// combining unflushed memtable records with on-disk SST key counts, so
+    /// `DeleteStrategy::Auto` can pick a deletion strategy without the
+    /// caller having to guess at the range's size.
+    fn estimate_range_keys_cf(&self, cf: &str, start_key: &[u8], end_key: &[u8]) -> Result<u64> {
+        let range = Range::new(start_key, end_key);
+        let (memtable_keys, _) = self.get_approximate_memtable_stats_cf(cf, &range)?;
+        let sst_keys = self.get_approximate_sst_keys_cf(cf, &range)?;
+        Ok(memtable_keys + sst_keys)
+    }
+
+    /// Applies `strategy` across every range in `ranges`, for every CF.
+    ///
+    /// Adjacent or overlapping ranges are coalesced into the minimal
+    /// disjoint set that still needs clearing first, so callers that pass
+    /// in many already-overlapping ranges (e.g. several destroyed peers'
+    /// key ranges queued up together) don't scan the same keys twice. Except
+    /// under `DeleteStrategy::Auto`, which handles each range on its own,
+    /// each CF then shares a single `WriteBatch` (or, for `DeleteByWriter`,
+    /// a single SST writer) across all of the coalesced ranges rather than
+    /// starting fresh per range, so destroy-peer and GC paths can clean up many
+    /// regions' worth of ranges in one pass instead of issuing N
+    /// independent `delete_all_in_range` calls.
+    ///
+    /// Returns one [`RangeDeleteReport`] per coalesced range, in start-key
+    /// order, so the caller can see what actually happened instead of just
+    /// `Ok(())`.
+    fn delete_all_in_ranges(
+        &self,
+        strategy: DeleteStrategy,
+        ranges: &[Range<'_>],
+    ) -> Result<Vec<RangeDeleteReport>> {
+        let mut sorted: Vec<(Vec<u8>, Vec<u8>)> = ranges
+            .iter()
+            .filter(|r| r.start_key < r.end_key)
+            .map(|r| (r.start_key.to_vec(), r.end_key.to_vec()))
+            .collect();
+        sorted.sort();
+
+        let mut coalesced: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for (start, end) in sorted {
+            match coalesced.last_mut() {
+                Some((_, last_end)) if start <= *last_end => {
+                    if end > *last_end {
+                        *last_end = end;
+                    }
+                }
+                _ => coalesced.push((start, end)),
+            }
+        }
+
+        let mut reports = vec![RangeDeleteReport::default(); coalesced.len()];
+        for cf in self.cf_names() {
+            self.delete_ranges_cf_with_reports(cf, strategy.clone(), &coalesced, &mut reports)?;
+        }
+        Ok(reports)
+    }
+
+    /// One CF's worth of `delete_all_in_ranges`, sharing a single
+    /// `WriteBatch`/SST writer across every range in `ranges` and folding
+    /// each range's scan/delete counts into the matching slot of `reports`.
+    fn delete_ranges_cf_with_reports(
+        &self,
+        cf: &str,
+        strategy: DeleteStrategy,
+        ranges: &[(Vec<u8>, Vec<u8>)],
+        reports: &mut [RangeDeleteReport],
+    ) -> Result<()> {
+        match strategy {
+            DeleteStrategy::DeleteByRange => {
+                let mut wb = self.write_batch();
+                for ((start_key, end_key), report) in ranges.iter().zip(reports.iter_mut()) {
+                    // `delete_range_cf` only writes a range tombstone, never
+                    // scanning the range, so there's no exact count to
+                    // report here - estimate instead of leaving the report
+                    // zeroed, which a caller would otherwise read as "there
+                    // was nothing to delete".
+                    let estimated_keys = self.estimate_range_keys_cf(cf, start_key, end_key)?;
+                    report.keys_scanned += estimated_keys;
+                    report.keys_deleted += estimated_keys;
+                    wb.delete_range_cf(cf, start_key, end_key)?;
+                }
+                if wb.count() > 0 {
+                    self.write(&wb)?;
+                }
+            }
+            DeleteStrategy::DeleteByKey => {
+                let mut wb = self.write_batch();
+                for ((start_key, end_key), report) in ranges.iter().zip(reports.iter_mut()) {
+                    let start = KeyBuilder::from_slice(start_key, 0, 0);
+                    let end = KeyBuilder::from_slice(end_key, 0, 0);
+                    let mut opts = IterOptions::new(Some(start), Some(end), false);
+                    if self.is_titan() {
+                        opts.set_key_only(true);
+                    }
+                    let mut it = self.iterator_cf_opt(cf, opts)?;
+                    let mut it_valid = it.seek(start_key.as_slice().into())?;
+                    while it_valid {
+                        report.keys_scanned += 1;
+                        report.bytes_deleted += it.key().len() as u64;
+                        wb.delete_cf(cf, it.key())?;
+                        report.keys_deleted += 1;
+                        if wb.count() >= MAX_DELETE_BATCH_SIZE {
+                            self.write(&wb)?;
+                            wb.clear();
+                        }
+                        it_valid = it.next()?;
+                    }
+                }
+                if wb.count() > 0 {
+                    self.write(&wb)?;
+                }
+            }
+            DeleteStrategy::DeleteByWriter { sst_path } => {
+                let mut data: Vec<Vec<u8>> = vec![];
+                for ((start_key, end_key), report) in ranges.iter().zip(reports.iter_mut()) {
+                    let start = KeyBuilder::from_slice(start_key, 0, 0);
+                    let end = KeyBuilder::from_slice(end_key, 0, 0);
+                    let mut opts = IterOptions::new(Some(start), Some(end), false);
+                    if self.is_titan() {
+                        opts.set_key_only(true);
+                    }
+                    let mut it = self.iterator_cf_opt(cf, opts)?;
+                    let mut it_valid = it.seek(start_key.as_slice().into())?;
+                    let before = data.len();
+                    while it_valid {
+                        report.keys_scanned += 1;
+                        report.bytes_deleted += it.key().len() as u64;
+                        data.push(it.key().to_vec());
+                        report.keys_deleted += 1;
+                        it_valid = it.next()?;
+                    }
+                    if data.len() > before {
+                        report.used_ingest = true;
+                    }
+                }
+                if data.is_empty() {
+                    return Ok(());
+                }
+                let builder = Self::SstWriterBuilder::new().set_db(self).set_cf(cf);
+                let mut writer = builder.build(sst_path.as_str())?;
+                for key in data.iter() {
+                    writer.delete(key).unwrap();
+                }
+                writer.finish()?;
+                let handle = self.cf_handle(cf)?;
+                let mut opt = Self::IngestExternalFileOptions::new();
+                opt.move_files(true);
+                self.ingest_external_file_cf(handle, &opt, &[sst_path.as_str()])?;
+            }
+            DeleteStrategy::Auto {
+                sst_path,
+                small_threshold,
+                large_threshold,
+            } => {
+                for ((start_key, end_key), report) in ranges.iter().zip(reports.iter_mut()) {
+                    let estimated_keys = self.estimate_range_keys_cf(cf, start_key, end_key)?;
+                    let picked = if estimated_keys <= small_threshold {
+                        DeleteStrategy::DeleteByKey
+                    } else if estimated_keys <= large_threshold {
+                        DeleteStrategy::DeleteByRange
+                    } else {
+                        DeleteStrategy::DeleteByWriter {
+                            sst_path: sst_path.clone(),
+                        }
+                    };
+                    self.delete_ranges_cf_with_reports(
+                        cf,
+                        picked,
+                        std::slice::from_ref(&(start_key.clone(), end_key.clone())),
+                        std::slice::from_mut(report),
+                    )?;
+                }
+            }
         }
         Ok(())
     }
@@ -185,6 +469,11 @@ pub trait MiscExt: Iterable + WriteBatchExt + CFNamesExt + SstExt + ImportExt {
     /// Return the approximate number of records and size in the range of memtables of the cf.
     fn get_approximate_memtable_stats_cf(&self, cf: &str, range: &Range) -> Result<(u64, u64)>;
 
+    /// Return the approximate number of keys in the range across this cf's
+    /// on-disk SST files, i.e. excluding the memtables covered by
+    /// `get_approximate_memtable_stats_cf`.
+    fn get_approximate_sst_keys_cf(&self, cf: &str, range: &Range) -> Result<u64>;
+
     fn ingest_maybe_slowdown_writes(&self, cf: &str) -> Result<bool>;
 
     /// Gets total used size of rocksdb engine, including:
@@ -217,6 +506,14 @@ pub trait MiscExt: Iterable + WriteBatchExt + CFNamesExt + SstExt + ImportExt {
     /// For debugging. The format and content is unspecified.
     fn dump_stats(&self) -> Result<String>;
 
+    /// Structured per-CF, per-level engine statistics, for telemetry.
+    ///
+    /// Unlike `dump_stats`, the shape here is stable: a caller iterates
+    /// `EngineStats` and pushes each field into a Prometheus registry
+    /// directly, with no text parsing involved. This is the supported path
+    /// for monitoring; `dump_stats` remains for human debugging.
+    fn get_engine_stats(&self) -> Result<EngineStats>;
+
     fn get_latest_sequence_number(&self) -> u64;
 
     fn get_oldest_snapshot_sequence_number(&self) -> Option<u64>;
@@ -2,32 +2,39 @@
 
 use std::borrow::Cow;
 use std::cmp::{Ord, Ordering as CmpOrdering};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 #[cfg(test)]
 use std::sync::mpsc::Sender;
 use std::sync::mpsc::SyncSender;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use std::{cmp, usize};
 
 use batch_system::{BasicMailbox, BatchRouter, BatchSystem, Fsm, HandlerBuilder, PollHandler};
-use crossbeam::channel::{TryRecvError, TrySendError};
+use crossbeam::channel::{self, TryRecvError, TrySendError};
 use engine_rocks::{RocksEngine, RocksSnapshot};
-use engine_traits::{KvEngine, MiscExt, Peekable, Snapshot, WriteBatch, WriteBatchVecExt};
+use engine_traits::{
+    Iterable, KvEngine, MiscExt, Peekable, Snapshot, WriteBatch, WriteBatchVecExt,
+};
 use engine_traits::{ALL_CFS, CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE};
 use kvproto::import_sstpb::SstMeta;
 use kvproto::metapb::{Peer as PeerMeta, Region, RegionEpoch};
 use kvproto::raft_cmdpb::{
-    AdminCmdType, AdminRequest, AdminResponse, ChangePeerRequest, CmdType, CommitMergeRequest,
-    RaftCmdRequest, RaftCmdResponse, Request, Response,
+    AdminCmdType, AdminRequest, AdminResponse, ChangePeerRequest, ChangePeerV2Request, CmdType,
+    CommitMergeRequest, RaftCmdRequest, RaftCmdResponse, Request, Response,
 };
 use kvproto::raft_serverpb::{
     MergeState, PeerState, RaftApplyState, RaftTruncatedState, RegionLocalState,
 };
-use raft::eraftpb::{ConfChange, ConfChangeType, Entry, EntryType, Snapshot as RaftSnapshot};
+use protobuf::Message;
+use raft::eraftpb::{
+    ConfChange, ConfChangeType, ConfChangeV2, Entry, EntryType, Snapshot as RaftSnapshot,
+};
 use uuid::Builder as UuidBuilder;
 
 use crate::coprocessor::{Cmd, CoprocessorHost};
@@ -57,6 +64,24 @@ const DEFAULT_APPLY_WB_SIZE: usize = 4 * 1024;
 const WRITE_BATCH_LIMIT: usize = 16;
 const APPLY_WB_SHRINK_SIZE: usize = 1024 * 1024;
 const SHRINK_PENDING_CMD_QUEUE_CAP: usize = 64;
+// Bounds how many write batches may be in flight to the pipelined flush
+// thread at once. Once full, `write_to_db` blocks the apply poller, turning
+// the pipeline into backpressure instead of unbounded memory growth.
+const DEFAULT_PIPELINED_WRITE_QUEUE_CAP: usize = 2;
+
+/// One write batch worth of pipelined flush work: the batch itself, the
+/// `ApplyRes`es it unblocks, and the callbacks it must fire once the batch is
+/// durable. `write_to_db` hands this off to the background flush thread and
+/// swaps in a fresh `kv_wb`, so the apply poller does not wait on the engine
+/// write (and its fsync, when `need_sync` is set) unless the bounded flush
+/// queue is already full.
+struct FlushTask<W> {
+    wb: W,
+    need_sync: bool,
+    timing: ApplyTimingDetail,
+    apply_res: Vec<ApplyRes>,
+    cbs: MustConsumeVec<ApplyCallback>,
+}
 
 pub struct PendingCmd {
     pub index: u64,
@@ -137,12 +162,189 @@ impl PendingCmdQueue {
     }
 }
 
+/// A single peer adjustment folded into a (possibly joint) change-peer
+/// command: `AddNode`/`AddLearnerNode`/`RemoveNode` plus the target peer.
+#[derive(Debug, Clone)]
+pub struct PeerChange {
+    pub change_type: ConfChangeType,
+    pub peer: PeerMeta,
+}
+
+/// The raft-level conf change request that produced a `ChangePeer` result.
+/// Kept as the original proto - rather than re-deriving it from `changes` -
+/// since the store side needs to hand the exact same message to raft-rs'
+/// `apply_conf_change`.
+#[derive(Debug)]
+pub enum ConfChangeRequest {
+    V1(ConfChange),
+    V2(ConfChangeV2),
+}
+
+impl Default for ConfChangeRequest {
+    fn default() -> ConfChangeRequest {
+        ConfChangeRequest::V2(ConfChangeV2::default())
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct ChangePeer {
     pub index: u64,
-    pub conf_change: ConfChange,
-    pub peer: PeerMeta,
+    pub conf_change: ConfChangeRequest,
+    /// Every single change folded into this command, in proposal order. A V1
+    /// change carries exactly one; a V2 joint-consensus change carries all N
+    /// `ConfChangeSingle`s it was proposed with, or none when it only
+    /// finalizes ("leaves") an already-entered joint configuration.
+    pub changes: Vec<PeerChange>,
+    pub region: Region,
+    /// Present for a simple `AddNode`/`AddLearnerNode` applied while this
+    /// region's data was already durable on local storage, letting the store
+    /// seed the new peer from `FastAddPeer` instead of a full RocksDB
+    /// snapshot. `None` means the fast path wasn't available (e.g. a joint
+    /// change, or pending writes not yet flushed) and the store must fall
+    /// back to generating a normal snapshot.
+    pub fast_add_peer: Option<FastAddPeer>,
+}
+
+/// Lightweight bootstrap info for a newly added peer, produced in place of a
+/// full snapshot. The store seeds the new replica with this `region` and
+/// `apply_state`, then fills in the gap with the raft log tail starting at
+/// `apply_state.get_applied_index()` instead of streaming a RocksDB
+/// snapshot.
+#[derive(Debug, Clone)]
+pub struct FastAddPeer {
     pub region: Region,
+    pub apply_state: RaftApplyState,
+}
+
+/// How long a deferred SST ingest is kept around waiting for this region's
+/// epoch to catch up before it's given up on and deleted.
+const PENDING_INGEST_SST_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How often `ApplyPoller::begin` re-sends `CatchUpLogs` for every pending
+/// `CommitMerge` wait still registered, so a wait survives a dropped
+/// `Msg::MergeSourceReady` or a restart without depending solely on the
+/// one-shot notification. Apply pollers run far more often than this, so the
+/// check is throttled by elapsed time rather than run on every poll.
+const PENDING_COMMIT_MERGE_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default cap on retries for an `IngestSst` that fails at the engine/
+/// importer boundary (as opposed to a stale epoch), used when
+/// `Config::max_ingest_attempts` is left at its zero-value default.
+const DEFAULT_MAX_INGEST_ATTEMPTS: u32 = 3;
+
+/// An SST whose ingest was deferred rather than rejected outright: either
+/// `check_sst_for_ingestion` failed on a stale epoch - the kind of failure a
+/// concurrent split, merge, or conf change routinely causes - rather than on
+/// the file itself being invalid, or the importer/engine ingest failed and
+/// is queued for another attempt (up to `max_ingest_attempts`).
+#[derive(Debug, Clone)]
+struct PendingIngestSst {
+    sst: SstMeta,
+    registered_at: Instant,
+    /// The index/term of the command that proposed this ingest, carried
+    /// along so a failure discovered on retry can still be attributed to
+    /// the entry that caused it in `IngestQuarantine`.
+    index: u64,
+    term: u64,
+}
+
+/// Whether a change-peer command is a plain single change, the start of a
+/// joint configuration, or the finalization ("leave") of one already
+/// entered. Mirrors raft's own `ConfChangeV2` semantics: zero changes means
+/// leave-joint, more than one means the changes must be applied atomically
+/// as a joint config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfChangeKind {
+    Simple,
+    EnterJoint,
+    LeaveJoint,
+}
+
+impl ConfChangeKind {
+    fn confchange_kind(changes_size: usize) -> ConfChangeKind {
+        match changes_size {
+            0 => ConfChangeKind::LeaveJoint,
+            1 => ConfChangeKind::Simple,
+            _ => ConfChangeKind::EnterJoint,
+        }
+    }
+}
+
+/// A region's write-availability role, layered on top of the persisted
+/// `PeerState` rather than replacing it. When `exec_prepare_merge` applies,
+/// the source region moves through `Downgrading` to `Merging`, so from then
+/// on it has an explicit, queryable state that rejects new writes instead of
+/// leaving clients to infer the pending merge from epoch-mismatch errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoleState {
+    /// Accepting proposals normally.
+    Writable,
+    /// Finalizing a merge as the source region: new writes should back off
+    /// and retry, typically against the region they're being folded into.
+    /// `exec_prepare_merge` passes through this state on its way to
+    /// `Merging`.
+    Downgrading,
+    /// `PeerState::Merging` is durable; the region is waiting for the
+    /// target to catch up and commit the merge.
+    Merging,
+    /// The region no longer exists.
+    Tombstone,
+}
+
+impl RoleState {
+    /// Whether `next` is a legal transition from this state. Only the
+    /// forward path `Writable -> Downgrading -> Merging -> Tombstone` is
+    /// allowed, plus the rollback path `Downgrading|Merging -> Writable`
+    /// that a failed merge takes back to normal operation.
+    fn can_transition_to(self, next: RoleState) -> bool {
+        use RoleState::*;
+        matches!(
+            (self, next),
+            (Writable, Downgrading)
+                | (Downgrading, Merging)
+                | (Merging, Tombstone)
+                | (Downgrading, Writable)
+                | (Merging, Writable)
+        )
+    }
+}
+
+/// A peer's leadership role, as seen by its own apply fsm. Distinct from
+/// [`RoleState`] (which tracks merge/split finalization): this tracks
+/// leader-transfer and pre-snapshot flush handoffs, and committed entries
+/// keep applying in every one of these states so Raft state stays
+/// consistent - only new *proposals* are affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionRoleState {
+    /// Accepting new proposals normally.
+    Leader,
+    /// Transferring leadership away, or flushing before a snapshot/merge:
+    /// committed entries still apply, but new proposals should be rejected
+    /// so clients retry against the new leader instead of being queued
+    /// behind a handoff that may never complete on this peer.
+    Downgrading,
+    /// No longer the leader.
+    Follower,
+}
+
+impl Default for RegionRoleState {
+    fn default() -> RegionRoleState {
+        RegionRoleState::Leader
+    }
+}
+
+impl RegionRoleState {
+    /// Only the forward path `Leader -> Downgrading -> Follower` is legal;
+    /// this never moves backward, so concurrent transfer-leadership and
+    /// flush requests converge on the same outcome regardless of delivery
+    /// order.
+    fn can_transition_to(self, next: RegionRoleState) -> bool {
+        use RegionRoleState::*;
+        matches!(
+            (self, next),
+            (Leader, Downgrading) | (Downgrading, Follower)
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -188,7 +390,7 @@ pub enum ExecResult {
     ComputeHash {
         region: Region,
         index: u64,
-        snap: RocksSnapshot,
+        digest: u64,
     },
     VerifyHash {
         index: u64,
@@ -208,10 +410,11 @@ pub enum ApplyResult {
     Yield,
     /// Additional result that needs to be sent back to raftstore.
     Res(ExecResult),
-    /// It is unable to apply the `CommitMerge` until the source peer
-    /// has applied to the required position and sets the atomic boolean
-    /// to true.
-    WaitMergeSource(Arc<AtomicU64>),
+    /// It is unable to apply the `CommitMerge` until the source peer has
+    /// applied to the required position. The target apply fsm is resumed by
+    /// `Msg::MergeSourceReady`, sent once the source peer's apply fsm
+    /// reaches that position, rather than by polling shared state.
+    WaitMergeSource,
 }
 
 struct ExecContext {
@@ -230,9 +433,75 @@ impl ExecContext {
     }
 }
 
+/// A per-region, order-independent consistency digest updated incrementally
+/// as writes and deletes are applied, so `exec_compute_hash` no longer needs
+/// to hold a long-lived RocksDB snapshot for the whole consistency check.
+///
+/// The digest is a state-based XOR accumulator: at any point it equals the
+/// XOR of `fnv64(cf, key, value)` over exactly the `(cf, key, value)` triples
+/// currently live in the engine - no more, no less. A put that overwrites an
+/// existing key therefore must un-fold the old value's term before folding
+/// in the new one, and a delete must un-fold whatever term is currently
+/// live instead of folding in a tombstone marker; `fold_put`/`fold_delete`
+/// take the key's prior value (a point read, done by the caller before the
+/// write lands) to do that. This is what makes `ApplyDelegate::
+/// rebuild_consistency_digest` valid: XOR-folding every currently-live
+/// `(cf, key, value)` from a full engine scan reconstructs exactly the same
+/// value the incrementally-maintained digest would hold, so a restart or
+/// snapshot install (which only has the scan, not the apply history) still
+/// converges to the value a peer that kept running would compute.
+///
+/// Whenever this region's key range changes (split, merge) the digest is
+/// reset to zero and marked incomplete, so the next `exec_compute_hash` on
+/// every replica rebuilds it from the engine over the new range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ConsistencyDigest(u64);
+
+impl ConsistencyDigest {
+    /// Folds in `(cf, key, value)`, first un-folding `old_value`'s term (if
+    /// this put is overwriting a live key) so the digest keeps reflecting
+    /// only the current state rather than accumulating every value a key
+    /// has ever held.
+    fn fold_put(&mut self, cf: &str, key: &[u8], value: &[u8], old_value: Option<&[u8]>) {
+        if let Some(old_value) = old_value {
+            self.0 ^= fnv64(&[cf.as_bytes(), key, old_value]);
+        }
+        self.0 ^= fnv64(&[cf.as_bytes(), key, value]);
+    }
+
+    /// Un-folds `old_value`'s term for a key that's being deleted. A live
+    /// scan never produces a term for a key that isn't there, so unlike the
+    /// old tombstone-marker scheme, a delete folds in nothing new - it only
+    /// cancels out the put this key's current value was folded in by.
+    fn fold_delete(&mut self, cf: &str, key: &[u8], old_value: Option<&[u8]>) {
+        if let Some(old_value) = old_value {
+            self.0 ^= fnv64(&[cf.as_bytes(), key, old_value]);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+/// FNV-1a over the concatenation of `parts`, used to fold apply-path writes
+/// into a [`ConsistencyDigest`] without pulling in a new hashing dependency.
+fn fnv64(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let mut hash = OFFSET;
+    for part in parts {
+        for &b in *part {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
 struct ApplyCallback {
     region: Region,
-    cbs: Vec<(Option<Callback<RocksEngine>>, RaftCmdResponse)>,
+    cbs: Vec<(Option<Callback<RocksEngine>>, RaftCmdResponse, ApplyDetail)>,
 }
 
 impl ApplyCallback {
@@ -242,19 +511,134 @@ impl ApplyCallback {
     }
 
     fn invoke_all(self, host: &CoprocessorHost) {
-        for (cb, mut resp) in self.cbs {
+        for (cb, mut resp, detail) in self.cbs {
             host.post_apply(&self.region, &mut resp);
+            detail.attach_to(&mut resp);
             if let Some(cb) = cb {
                 cb.invoke_with_response(resp)
             };
         }
     }
 
-    fn push(&mut self, cb: Option<Callback<RocksEngine>>, resp: RaftCmdResponse) {
-        self.cbs.push((cb, resp));
+    fn push(
+        &mut self,
+        cb: Option<Callback<RocksEngine>>,
+        resp: RaftCmdResponse,
+        detail: ApplyDetail,
+    ) {
+        self.cbs.push((cb, resp, detail));
+    }
+
+    /// Fills in the portion of every queued command's detail that's only
+    /// known once the batch has actually been written: engine write time,
+    /// batch size, and whether it was synced. Shared across every command in
+    /// the batch, since the write happens once per flush, not once per
+    /// command. Must be called before `invoke_all`.
+    fn fill_write_detail(
+        &mut self,
+        write_nanos: u64,
+        written_bytes: u64,
+        written_keys: u64,
+        synced: bool,
+    ) {
+        for (_, _, detail) in &mut self.cbs {
+            detail.write_nanos = write_nanos;
+            detail.written_bytes = written_bytes;
+            detail.written_keys = written_keys;
+            detail.synced = synced;
+        }
+    }
+}
+
+/// Per-flushed-batch apply-phase latency breakdown.
+///
+/// This mirrors the `write_detail` populated on the write-flow path
+/// (`tracker.write_write_detail`), but for the apply stage, which previously
+/// only surfaced the coarse `STORE_APPLY_LOG_HISTOGRAM`. The peer layer is
+/// expected to copy these nanos onto the `write_detail` fields of the
+/// responses it hands back to clients so operators can attribute tail write
+/// latency to a specific apply sub-stage instead of guessing.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ApplyTimingDetail {
+    /// Time spent waiting in the apply mailbox before `prepare_for` is reached.
+    pub apply_batch_wait_nanos: u64,
+    /// Time spent executing committed entries in `handle_raft_committed_entries`.
+    pub apply_log_nanos: u64,
+    /// Time spent building (allocating or resetting) the `kv_wb` write batch.
+    pub apply_wb_build_nanos: u64,
+    /// Time spent inside the RocksDB write call when `need_sync` forced a WAL fsync.
+    ///
+    /// The engine write call does not expose the fsync portion separately, so
+    /// the whole duration is attributed here when a sync was requested and to
+    /// `apply_write_memtable_nanos` otherwise.
+    pub apply_write_wal_nanos: u64,
+    /// Time spent inside the RocksDB write call when no fsync was required.
+    pub apply_write_memtable_nanos: u64,
+    /// Time spent running coprocessor `post_apply`/callback invocation.
+    pub commit_log_nanos: u64,
+}
+
+impl ApplyTimingDetail {
+    /// Accumulates `other` into `self`, field by field.
+    ///
+    /// Used to fold the `ApplyContext`-level timing for a flushed batch (wait
+    /// time, write-batch build time, engine write time, coprocessor flush
+    /// time) into the per-region `ApplyMetrics::timing` carried on each
+    /// `ApplyRes`, so a region's breakdown keeps whatever it already
+    /// accumulated across `yield_state` resumes within the same apply round.
+    fn add(&mut self, other: &ApplyTimingDetail) {
+        self.apply_batch_wait_nanos += other.apply_batch_wait_nanos;
+        self.apply_log_nanos += other.apply_log_nanos;
+        self.apply_wb_build_nanos += other.apply_wb_build_nanos;
+        self.apply_write_wal_nanos += other.apply_write_wal_nanos;
+        self.apply_write_memtable_nanos += other.apply_write_memtable_nanos;
+        self.commit_log_nanos += other.commit_log_nanos;
+    }
+}
+
+/// Per-command portion of the apply-phase detail, carried alongside its
+/// `RaftCmdResponse` in `ApplyCallback` rather than totalled over a whole
+/// flushed batch like `ApplyTimingDetail` is.
+#[derive(Clone, Debug, Default)]
+pub struct ApplyDetail {
+    /// Time this command's entry spent waiting in the apply mailbox before
+    /// `handle_apply` picked it up, sampled from
+    /// `ApplyContext::timing.apply_batch_wait_nanos` when the command was
+    /// taken off `PendingCmdQueue`.
+    pub wait_nanos: u64,
+    /// Time spent writing the `kv_wb` batch that included this command to
+    /// the engine. Shared by every command in that batch, since the engine
+    /// write and sync happen once per flush, not once per command.
+    pub write_nanos: u64,
+    /// Size in bytes of the `kv_wb` batch that included this command.
+    pub written_bytes: u64,
+    /// Number of keys in the `kv_wb` batch that included this command.
+    pub written_keys: u64,
+    /// Whether that batch was synced to the Raft WAL.
+    pub synced: bool,
+}
+
+impl ApplyDetail {
+    /// Copies the apply-owned fields onto `resp`'s `write_detail`, leaving
+    /// whatever the propose/write path already filled in (store batch wait,
+    /// raft log persist, ...) untouched, so the client sees a complete
+    /// breakdown once the response makes its way back through the peer.
+    fn attach_to(&self, resp: &mut RaftCmdResponse) {
+        let wd = resp.mut_exec_details_v2().mut_write_detail();
+        wd.set_apply_batch_wait_nanos(self.wait_nanos);
+        if self.synced {
+            wd.set_apply_write_wal_nanos(self.write_nanos);
+        } else {
+            wd.set_apply_write_memtable_nanos(self.write_nanos);
+        }
     }
 }
 
+#[inline]
+fn duration_to_nanos(d: std::time::Duration) -> u64 {
+    d.as_nanos() as u64
+}
+
 #[derive(Clone)]
 pub enum Notifier {
     Router(RaftRouter<RocksEngine>),
@@ -274,7 +658,7 @@ impl Notifier {
     }
 }
 
-struct ApplyContext<W: WriteBatch + WriteBatchVecExt<RocksEngine>> {
+struct ApplyContext<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static> {
     tag: String,
     timer: Option<Instant>,
     host: CoprocessorHost,
@@ -293,15 +677,76 @@ struct ApplyContext<W: WriteBatch + WriteBatchVecExt<RocksEngine>> {
     last_applied_index: u64,
     committed_count: usize,
 
+    // Per-tick byte budget for a single delegate's writes, refreshed from
+    // `Config::apply_bytes_per_tick` in `ApplyPoller::begin`. Zero disables
+    // the check, same convention as `messages_per_tick`.
+    apply_bytes_per_tick: u64,
+
+    // Apply-phase latency breakdown for the batch currently being flushed.
+    timing: ApplyTimingDetail,
+
     // Indicates that WAL can be synchronized when data is written to KV engine.
     enable_sync_log: bool,
     // Whether synchronize WAL is preferred.
     sync_log_hint: bool,
     // Whether to use the delete range API instead of deleting one by one.
     use_delete_range: bool,
+
+    // Whether `write_to_db` hands batches off to `flush_thread` instead of
+    // writing (and possibly fsyncing) them inline on the apply poller.
+    pipelined_write: bool,
+    // Channel to the background flush thread, present only when
+    // `pipelined_write` is enabled. Kept separate from `pipelined_write` so a
+    // failed thread spawn degrades to the synchronous path instead of panicking.
+    flush_tx: Option<channel::Sender<FlushTask<W>>>,
+    // The flush thread's other end of the hand-back: once it's done writing
+    // a batch, it clears it and sends it back here so a later
+    // `flush_pipelined` can reuse it instead of allocating a fresh one.
+    spare_rx: Option<channel::Receiver<W>>,
+    // The second `kv_wb`: while one batch is in flight to the flush thread,
+    // apply keeps writing into this one, then swaps back once it's free
+    // again - either because `spare_rx` handed the just-flushed batch back,
+    // or (the first time, before any flush has completed) a fresh one.
+    kv_wb_spare: Option<W>,
+
+    // Content-addressed chunk store shared by every apply poller in the
+    // store, used to deduplicate bytes across ingested SSTs.
+    chunk_store: Arc<content_chunking::ChunkStore>,
+
+    // Registry of per-region "already initialized" flags, shared by every
+    // apply poller in the store, consulted by `GenSnapTask::generate_and_schedule_snapshot`
+    // to skip a redundant snapshot for a peer that's already been bootstrapped.
+    cached_region_info: CachedRegionInfoManager,
+
+    // Dead-letter queue for `IngestSst` commands that keep failing at the
+    // engine/importer boundary, shared by every apply poller in the store so
+    // an operator has one place to enumerate them regardless of which
+    // poller's region hit the failure.
+    ingest_quarantine: Arc<ingest_quarantine::IngestQuarantine>,
+    // Cap on ingest attempts before an entry in `ingest_quarantine` is given
+    // up on; refreshed from `Config::max_ingest_attempts` in
+    // `ApplyPoller::begin`.
+    max_ingest_attempts: u32,
+
+    // Registry of per-region Merkle trees shared by every apply poller in
+    // the store, so `merkle_root`/`merkle_children` can be queried for any
+    // region regardless of which poller is applying it.
+    region_merkle: RegionMerkleManager,
+
+    // Per-peer chunk dedup state shared by every apply poller in the store,
+    // consulted by `GenSnapTask::generate_and_schedule_snapshot` so the
+    // same peer's earlier snapshots are recognized regardless of which
+    // poller generated them.
+    snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry,
+
+    // Registry of per-region append-only Merkle accumulators over applied
+    // entries, shared by every apply poller in the store, so a CDC or
+    // replication consumer can query `root`/`prove` for any region
+    // regardless of which poller is applying it - mirrors `region_merkle`.
+    entry_log: EntryAccumulatorManager,
 }
 
-impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
+impl<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static> ApplyContext<W> {
     pub fn new(
         tag: String,
         host: CoprocessorHost,
@@ -310,7 +755,25 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
         router: ApplyRouter,
         notifier: Notifier,
         cfg: &Config,
+        chunk_store: Arc<content_chunking::ChunkStore>,
+        cached_region_info: CachedRegionInfoManager,
+        ingest_quarantine: Arc<ingest_quarantine::IngestQuarantine>,
+        region_merkle: RegionMerkleManager,
+        snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry,
+        entry_log: EntryAccumulatorManager,
     ) -> ApplyContext<W> {
+        let (flush_tx, spare_rx) = if cfg.pipelined_write {
+            let (flush_tx, spare_rx) = Self::spawn_flush_thread(
+                &tag,
+                host.clone(),
+                engine.clone(),
+                notifier.clone(),
+                cfg.pipelined_write_queue_cap,
+            );
+            (Some(flush_tx), Some(spare_rx))
+        } else {
+            (None, None)
+        };
         ApplyContext::<W> {
             tag,
             timer: None,
@@ -326,13 +789,107 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
             kv_wb_last_keys: 0,
             last_applied_index: 0,
             committed_count: 0,
+            apply_bytes_per_tick: cfg.apply_bytes_per_tick,
+            timing: ApplyTimingDetail::default(),
             enable_sync_log: cfg.sync_log,
             sync_log_hint: false,
             exec_ctx: None,
             use_delete_range: cfg.use_delete_range,
+            pipelined_write: cfg.pipelined_write,
+            flush_tx,
+            spare_rx,
+            kv_wb_spare: None,
+            chunk_store,
+            cached_region_info,
+            ingest_quarantine,
+            max_ingest_attempts: if cfg.max_ingest_attempts > 0 {
+                cfg.max_ingest_attempts
+            } else {
+                DEFAULT_MAX_INGEST_ATTEMPTS
+            },
+            region_merkle,
+            snapshot_chunks,
+            entry_log,
         }
     }
 
+    /// Spawns the background thread that pipelined `write_to_db` hands
+    /// batches off to. The thread writes each batch to `engine`, runs the
+    /// coprocessor flush hook, invokes the batch's callbacks, and notifies
+    /// the batch's `ApplyRes`, all in the order batches were enqueued, so
+    /// apply-state durability ordering is preserved across regions. Once a
+    /// batch is written, it's cleared and handed back over the returned
+    /// receiver so `flush_pipelined` can reuse its allocation instead of
+    /// building a fresh `kv_wb` on every flush.
+    fn spawn_flush_thread(
+        tag: &str,
+        host: CoprocessorHost,
+        engine: RocksEngine,
+        notifier: Notifier,
+        queue_cap: usize,
+    ) -> (channel::Sender<FlushTask<W>>, channel::Receiver<W>) {
+        let cap = if queue_cap == 0 {
+            DEFAULT_PIPELINED_WRITE_QUEUE_CAP
+        } else {
+            queue_cap
+        };
+        let (tx, rx) = channel::bounded::<FlushTask<W>>(cap);
+        // Only one batch is ever "spare" at a time (`kv_wb_spare`), so the
+        // hand-back channel never needs to hold more than one.
+        let (spare_tx, spare_rx) = channel::bounded::<W>(1);
+        thread::Builder::new()
+            .name(format!("{}-apply-flush", tag))
+            .spawn(move || {
+                for mut task in rx {
+                    let mut write_opts = engine_traits::WriteOptions::new();
+                    write_opts.set_sync(task.need_sync);
+                    let written_bytes = task.wb.data_size() as u64;
+                    let written_keys = task.wb.count() as u64;
+                    let write_begin = Instant::now_coarse();
+                    task.wb
+                        .write_to_engine(&engine, &write_opts)
+                        .unwrap_or_else(|e| {
+                            panic!("failed to write to engine in pipelined flush: {:?}", e);
+                        });
+                    let write_nanos = duration_to_nanos(write_begin.elapsed());
+                    host.on_flush_apply();
+                    for mut cbs in task.cbs.drain(..) {
+                        cbs.fill_write_detail(
+                            write_nanos,
+                            written_bytes,
+                            written_keys,
+                            task.need_sync,
+                        );
+                        cbs.invoke_all(&host);
+                    }
+                    for mut res in task.apply_res {
+                        res.metrics.timing.add(&task.timing);
+                        notifier.notify(
+                            res.region_id,
+                            PeerMsg::ApplyRes {
+                                res: TaskRes::Apply(res),
+                            },
+                        );
+                    }
+                    // Mirror `write_to_db`'s synchronous-path shrink check:
+                    // don't hand a batch that's grown unusually large back
+                    // for reuse, or `kv_wb_spare` would pin that memory for
+                    // as long as the apply poller keeps recycling it.
+                    if task.wb.data_size() > APPLY_WB_SHRINK_SIZE {
+                        continue;
+                    }
+                    task.wb.clear();
+                    // Best-effort: if the poller hasn't drained the
+                    // previous hand-back yet (or has gone away), dropping
+                    // this batch just costs one allocation later, not
+                    // correctness.
+                    let _ = spare_tx.try_send(task.wb);
+                }
+            })
+            .unwrap();
+        (tx, spare_rx)
+    }
+
     /// Prepares for applying entries for `delegate`.
     ///
     /// A general apply progress for a delegate is:
@@ -343,15 +900,20 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
         self.cbs.push(ApplyCallback::new(delegate.region.clone()));
         self.last_applied_index = delegate.apply_state.get_applied_index();
 
-        if let Some(observe_cmd) = &delegate.observe_cmd {
+        if !delegate.observe_cmds.is_empty() {
             let region_id = delegate.region_id();
-            if observe_cmd.enabled.load(Ordering::Acquire) {
-                self.host.prepare_for_apply(observe_cmd.id, region_id);
-            } else {
-                info!("region is no longer observerd";
-                    "region_id" => region_id);
-                delegate.observe_cmd.take();
-            }
+            let host = &self.host;
+            delegate.observe_cmds.retain(|_, observe_cmd| {
+                if observe_cmd.enabled.load(Ordering::Acquire) {
+                    host.prepare_for_apply(observe_cmd.id, region_id);
+                    true
+                } else {
+                    info!("region is no longer observerd";
+                        "region_id" => region_id,
+                        "observe_id" => ?observe_cmd.id);
+                    false
+                }
+            });
         }
     }
 
@@ -361,10 +923,12 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
     /// Otherwise create `RocksWriteBatch`.
     pub fn prepare_write_batch(&mut self) {
         if self.kv_wb.is_none() {
+            let build_begin = Instant::now_coarse();
             let kv_wb = W::write_batch_vec(&self.engine, WRITE_BATCH_LIMIT, DEFAULT_APPLY_WB_SIZE);
             self.kv_wb = Some(kv_wb);
             self.kv_wb_last_bytes = 0;
             self.kv_wb_last_keys = 0;
+            self.timing.apply_wb_build_nanos += duration_to_nanos(build_begin.elapsed());
         }
     }
 
@@ -395,16 +959,41 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
     /// If it returns true, all pending writes are persisted in engines.
     pub fn write_to_db(&mut self) -> bool {
         let need_sync = self.enable_sync_log && self.sync_log_hint;
+        if self.pipelined_write
+            && self.flush_tx.is_some()
+            && self.kv_wb.as_ref().map_or(false, |wb| !wb.is_empty())
+        {
+            self.flush_pipelined(need_sync);
+            // The batch hasn't landed on disk yet, only been handed to the
+            // flush thread, so we can't claim it's synced. Durability for
+            // callers is still guaranteed: the flush thread only fires
+            // callbacks and `ApplyRes` notifications after the write
+            // completes, in the order batches were enqueued.
+            return false;
+        }
+        let mut written_bytes = 0;
+        let mut written_keys = 0;
+        let mut write_nanos = 0;
         if self.kv_wb.as_ref().map_or(false, |wb| !wb.is_empty()) {
+            written_bytes = self.kv_wb().data_size() as u64;
+            written_keys = self.kv_wb().count() as u64;
             let mut write_opts = engine_traits::WriteOptions::new();
             write_opts.set_sync(need_sync);
+            let write_begin = Instant::now_coarse();
             self.kv_wb()
                 .write_to_engine(&self.engine, &write_opts)
                 .unwrap_or_else(|e| {
                     panic!("failed to write to engine: {:?}", e);
                 });
+            write_nanos = duration_to_nanos(write_begin.elapsed());
+            if need_sync {
+                self.timing.apply_write_wal_nanos += write_nanos;
+            } else {
+                self.timing.apply_write_memtable_nanos += write_nanos;
+            }
             self.sync_log_hint = false;
             let data_size = self.kv_wb().data_size();
+            let rebuild_begin = Instant::now_coarse();
             if data_size > APPLY_WB_SHRINK_SIZE {
                 // Control the memory usage for the WriteBatch.
                 let kv_wb =
@@ -414,18 +1003,56 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
                 // Clear data, reuse the WriteBatch, this can reduce memory allocations and deallocations.
                 self.kv_wb_mut().clear();
             }
+            self.timing.apply_wb_build_nanos += duration_to_nanos(rebuild_begin.elapsed());
             self.kv_wb_last_bytes = 0;
             self.kv_wb_last_keys = 0;
         }
         // Call it before invoking callback for preventing Commit is executed before Prewrite is observed.
+        let commit_log_begin = Instant::now_coarse();
         self.host.on_flush_apply();
+        self.timing.commit_log_nanos += duration_to_nanos(commit_log_begin.elapsed());
 
-        for cbs in self.cbs.drain(..) {
+        for mut cbs in self.cbs.drain(..) {
+            cbs.fill_write_detail(write_nanos, written_bytes, written_keys, need_sync);
             cbs.invoke_all(&self.host);
         }
         need_sync
     }
 
+    /// Hands `kv_wb` off to the background flush thread and swaps in
+    /// `kv_wb_spare` (or a freshly allocated batch) so apply can keep going
+    /// without waiting for this batch to land on disk. Only called once
+    /// `kv_wb` is known to be non-empty.
+    fn flush_pipelined(&mut self, need_sync: bool) {
+        let wb = self.kv_wb.take().unwrap();
+        if self.kv_wb_spare.is_none() {
+            self.kv_wb_spare = self.spare_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+        }
+        self.kv_wb = Some(self.kv_wb_spare.take().unwrap_or_else(|| {
+            W::write_batch_vec(&self.engine, WRITE_BATCH_LIMIT, DEFAULT_APPLY_WB_SIZE)
+        }));
+        self.kv_wb_last_bytes = 0;
+        self.kv_wb_last_keys = 0;
+        self.sync_log_hint = false;
+
+        let task = FlushTask {
+            wb,
+            need_sync,
+            timing: std::mem::take(&mut self.timing),
+            apply_res: self.apply_res.drain(..).collect(),
+            cbs: std::mem::replace(
+                &mut self.cbs,
+                MustConsumeVec::new("callback of apply context"),
+            ),
+        };
+        // A bounded channel means this blocks once the flush thread falls
+        // behind, which is the backpressure that keeps in-flight batches
+        // bounded instead of piling up unboundedly.
+        if self.flush_tx.as_ref().unwrap().send(task).is_err() {
+            panic!("{} apply flush thread has gone away", self.tag);
+        }
+    }
+
     /// Finishes `Apply`s for the delegate.
     pub fn finish_for(&mut self, delegate: &mut ApplyDelegate, results: VecDeque<ExecResult>) {
         if !delegate.pending_remove {
@@ -476,7 +1103,8 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
         let is_synced = self.write_to_db();
 
         if !self.apply_res.is_empty() {
-            for res in self.apply_res.drain(..) {
+            for mut res in self.apply_res.drain(..) {
+                res.metrics.timing.add(&self.timing);
                 self.notifier.notify(
                     res.region_id,
                     PeerMsg::ApplyRes {
@@ -485,6 +1113,7 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> ApplyContext<W> {
                 );
             }
         }
+        self.timing = ApplyTimingDetail::default();
 
         let elapsed = t.elapsed();
         STORE_APPLY_LOG_HISTOGRAM.observe(duration_to_sec(elapsed) as f64);
@@ -539,11 +1168,8 @@ pub fn notify_stale_req(term: u64, cb: Callback<RocksEngine>) {
 fn should_write_to_engine(cmd: &RaftCmdRequest) -> bool {
     if cmd.has_admin_request() {
         match cmd.get_admin_request().get_cmd_type() {
-            // ComputeHash require an up to date snapshot.
-            AdminCmdType::ComputeHash |
             // Merge needs to get the latest apply index.
-            AdminCmdType::CommitMerge |
-            AdminCmdType::RollbackMerge => return true,
+            AdminCmdType::CommitMerge | AdminCmdType::RollbackMerge => return true,
             _ => {}
         }
     }
@@ -580,22 +1206,6 @@ fn should_sync_log(cmd: &RaftCmdRequest) -> bool {
     false
 }
 
-/// A struct that stores the state related to Merge.
-///
-/// When executing a `CommitMerge`, the source peer may have not applied
-/// to the required index, so the target peer has to abort current execution
-/// and wait for it asynchronously.
-///
-/// When rolling the stack, all states required to recover are stored in
-/// this struct.
-/// TODO: check whether generator/coroutine is a good choice in this case.
-struct WaitSourceMergeState {
-    /// A flag that indicates whether the source peer has applied to the required
-    /// index. If the source peer is ready, this flag should be set to the region id
-    /// of source peer.
-    logs_up_to_date: Arc<AtomicU64>,
-}
-
 struct YieldState {
     /// All of the entries that need to continue to be applied after
     /// the source peer has applied its logs.
@@ -615,14 +1225,6 @@ impl Debug for YieldState {
     }
 }
 
-impl Debug for WaitSourceMergeState {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("WaitSourceMergeState")
-            .field("logs_up_to_date", &self.logs_up_to_date)
-            .finish()
-    }
-}
-
 /// The apply delegate of a Region which is responsible for handling committed
 /// raft log entries of a Region.
 ///
@@ -651,6 +1253,11 @@ pub struct ApplyDelegate {
     /// A delegate can be stopped in conf change, merge or requested by destroy message.
     stopped: bool,
     written: bool,
+    /// Bytes written to `ApplyContext::kv_wb` by this delegate since the
+    /// current tick started (reset in `ApplyPoller::handle_normal`), used to
+    /// yield once `Config::apply_bytes_per_tick` is exceeded so one region's
+    /// large writes can't starve its peers on the same apply poller.
+    tick_written_bytes: u64,
     /// Set to true when removing itself because of `ConfChangeType::RemoveNode`, and then
     /// any following committed logs in same Ready should be applied failed.
     pending_remove: bool,
@@ -662,15 +1269,66 @@ pub struct ApplyDelegate {
 
     /// Indicates the peer is in merging, if that compact log won't be performed.
     is_merging: bool,
+    /// Set while the region's configuration is a joint one, i.e. an
+    /// `EnterJoint` change-peer command has been applied but the matching
+    /// `LeaveJoint` hasn't yet.
+    in_joint_state: bool,
     /// Records the epoch version after the last merge.
     last_merge_version: u64,
+    /// Write-availability role layered on top of `PeerState`; see
+    /// [`RoleState`].
+    role_state: RoleState,
+    /// Leadership role as set by `Msg::SetRoleState`; see
+    /// [`RegionRoleState`].
+    leader_role_state: RegionRoleState,
     yield_state: Option<YieldState>,
-    /// A temporary state that keeps track of the progress of the source peer state when
-    /// CommitMerge is unable to be executed.
-    wait_merge_state: Option<WaitSourceMergeState>,
+    /// Set while executing a `CommitMerge` whose source peer hasn't applied
+    /// to the required index yet. Cleared only by `on_merge_source_ready`,
+    /// which the source peer's apply fsm triggers directly via
+    /// `Msg::MergeSourceReady` once it catches up - there's no polling of
+    /// shared state involved.
+    wait_merge_state: bool,
     // ID of last region that reports ready.
     ready_source_region_id: u64,
 
+    /// SSTs rejected by `check_sst_for_ingestion` on an epoch that was
+    /// stale rather than the file being corrupt, e.g. a concurrent split or
+    /// merge moved on while the ingest was in flight, plus ingests that
+    /// failed at the importer/engine and await another attempt. Re-checked every time
+    /// this delegate's region epoch advances, instead of being deleted
+    /// immediately.
+    pending_ingest_ssts: Vec<PendingIngestSst>,
+
+    /// SSTs this delegate has successfully ingested and chunked into
+    /// `content_chunking::ChunkStore`, kept around so `handle_delete_range`
+    /// and `destroy` can release their chunk references once the data is
+    /// gone - otherwise the store's ref-counts only ever grow. An SST is
+    /// removed from here as soon as it's released.
+    chunked_ssts: Vec<SstMeta>,
+
+    /// Running, order-independent consistency digest; see
+    /// [`ConsistencyDigest`].
+    consistency_digest: ConsistencyDigest,
+    /// Whether `consistency_digest` reflects every key this region's range
+    /// currently holds, as opposed to only what's been folded in since this
+    /// delegate was last (re)created from `Registration` - i.e. since the
+    /// last restart or snapshot install - or since a split or merge last
+    /// changed this region's range. `exec_compute_hash` rebuilds the
+    /// digest from the actual engine content the first time it's needed
+    /// while this is `false`, so a replica that just restarted doesn't
+    /// report a false mismatch against peers that never did.
+    digest_is_complete: bool,
+
+    /// Whether this region's [`region_merkle::RegionMerkle`] (in
+    /// `RegionMerkleManager`) reflects every key this region's range
+    /// currently holds, for the same reason and on the same triggers as
+    /// `digest_is_complete` - see `rebuild_region_merkle`. Like the digest,
+    /// the tree's leaves hold only the latest hash per live key, so
+    /// rebuilding it from a live-key engine
+    /// scan reproduces exactly what a continuously-running peer's tree
+    /// would hold.
+    merkle_is_complete: bool,
+
     /// TiKV writes apply_state to KV RocksDB, in one write batch together with kv data.
     ///
     /// If we write it to Raft RocksDB, apply_state and kv data (Put, Delete) are in
@@ -682,8 +1340,10 @@ pub struct ApplyDelegate {
     /// The latest synced apply index.
     last_sync_apply_index: u64,
 
-    /// Info about cmd observer.
-    observe_cmd: Option<ObserveCmd>,
+    /// Active cmd observers for this region, keyed by `ObserveID`, so CDC,
+    /// resolved-ts, and incremental backup can each subscribe to the same
+    /// region's command stream at once instead of fighting over one slot.
+    observe_cmds: HashMap<ObserveID, ObserveCmd>,
 
     /// The local metrics, and it will be flushed periodically.
     metrics: ApplyMetrics,
@@ -702,15 +1362,30 @@ impl ApplyDelegate {
             term: reg.term,
             stopped: false,
             written: false,
+            tick_written_bytes: 0,
             ready_source_region_id: 0,
             yield_state: None,
-            wait_merge_state: None,
+            wait_merge_state: false,
             is_merging: reg.is_merging,
+            in_joint_state: false,
             pending_cmds: Default::default(),
             metrics: Default::default(),
             last_merge_version: 0,
+            role_state: RoleState::Writable,
+            leader_role_state: RegionRoleState::Leader,
+            pending_ingest_ssts: Vec::new(),
+            chunked_ssts: Vec::new(),
+            consistency_digest: ConsistencyDigest::default(),
+            // `reg` may carry state that predates this delegate (a restart
+            // or a snapshot install), which this freshly-zeroed digest
+            // hasn't folded in yet.
+            digest_is_complete: false,
+            // Same rationale as `digest_is_complete`: `reg` may carry a
+            // region whose tree was last built by a delegate that no longer
+            // exists.
+            merkle_is_complete: false,
             pending_request_snapshot_count: reg.pending_request_snapshot_count,
-            observe_cmd: None,
+            observe_cmds: HashMap::default(),
         }
     }
 
@@ -722,6 +1397,68 @@ impl ApplyDelegate {
         self.id
     }
 
+    /// Advances `role_state` to `next` if that's a legal transition (see
+    /// [`RoleState::can_transition_to`]), logging and ignoring the request
+    /// otherwise rather than panicking - a stale or duplicate admin command
+    /// replaying this transition should not crash the apply loop.
+    fn set_role_state_gracefully(&mut self, next: RoleState) {
+        if self.role_state == next {
+            return;
+        }
+        if !self.role_state.can_transition_to(next) {
+            warn!(
+                "ignoring illegal role state transition";
+                "region_id" => self.region_id(),
+                "peer_id" => self.id(),
+                "from" => ?self.role_state,
+                "to" => ?next,
+            );
+            return;
+        }
+        self.role_state = next;
+    }
+
+    /// Advances `leader_role_state` to `next` if that's a legal,
+    /// forward-only transition (see
+    /// [`RegionRoleState::can_transition_to`]); ignored otherwise so a
+    /// duplicate or stale `Msg::SetRoleState` can't move the delegate
+    /// backward.
+    fn set_leader_role_state_gracefully(&mut self, next: RegionRoleState) {
+        if self.leader_role_state == next {
+            return;
+        }
+        if !self.leader_role_state.can_transition_to(next) {
+            warn!(
+                "ignoring illegal leader role state transition";
+                "region_id" => self.region_id(),
+                "peer_id" => self.id(),
+                "from" => ?self.leader_role_state,
+                "to" => ?next,
+            );
+            return;
+        }
+        self.leader_role_state = next;
+    }
+
+    /// Whether incoming writes to this region should be rejected so the
+    /// client backs off and retries - either because this delegate is
+    /// finalizing a merge ([`RoleState`]) or because this peer is
+    /// handing off leadership ([`RegionRoleState`]) - instead of the write
+    /// being silently applied or queued behind a handoff.
+    pub fn should_reject_write(&self) -> bool {
+        !matches!(self.role_state, RoleState::Writable)
+            || !matches!(self.leader_role_state, RegionRoleState::Leader)
+    }
+
+    /// Whether this region's applied state and the writes it covers are
+    /// already durable on local storage, i.e. `last_sync_apply_index` has
+    /// caught up with `apply_state`. A newly added peer can only be
+    /// fast-bootstrapped (see `FastAddPeer`) while this holds; otherwise the
+    /// store must fall back to a full snapshot.
+    fn data_is_durable(&self) -> bool {
+        self.last_sync_apply_index == self.apply_state.get_applied_index()
+    }
+
     /// Handles all the committed_entries, namely, applies the committed entries.
     fn handle_raft_committed_entries<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
         &mut self,
@@ -767,7 +1504,9 @@ impl ApplyDelegate {
             let res = match entry.get_entry_type() {
                 EntryType::EntryNormal => self.handle_raft_entry_normal(apply_ctx, &entry),
                 EntryType::EntryConfChange => self.handle_raft_entry_conf_change(apply_ctx, &entry),
-                EntryType::EntryConfChangeV2 => unimplemented!(),
+                EntryType::EntryConfChangeV2 => {
+                    self.handle_raft_entry_conf_change_v2(apply_ctx, &entry)
+                }
             };
 
             match res {
@@ -785,8 +1524,8 @@ impl ApplyDelegate {
                         pending_entries,
                         pending_msgs: Vec::default(),
                     });
-                    if let ApplyResult::WaitMergeSource(logs_up_to_date) = res {
-                        self.wait_merge_state = Some(WaitSourceMergeState { logs_up_to_date });
+                    if let ApplyResult::WaitMergeSource = res {
+                        self.wait_merge_state = true;
                     }
                     return;
                 }
@@ -804,8 +1543,10 @@ impl ApplyDelegate {
         &mut self,
         apply_ctx: &ApplyContext<W>,
     ) {
-        self.metrics.written_bytes += apply_ctx.delta_bytes();
+        let delta_bytes = apply_ctx.delta_bytes();
+        self.metrics.written_bytes += delta_bytes;
         self.metrics.written_keys += apply_ctx.delta_keys();
+        self.tick_written_bytes += delta_bytes;
     }
 
     fn write_apply_state<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(&self, wb: &mut W) {
@@ -840,7 +1581,16 @@ impl ApplyDelegate {
 
             if should_write_to_engine(&cmd) || apply_ctx.kv_wb().should_write_to_engine() {
                 apply_ctx.commit(self);
+                if apply_ctx.apply_bytes_per_tick > 0
+                    && self.tick_written_bytes >= apply_ctx.apply_bytes_per_tick
+                {
+                    APPLY_YIELD_COUNTER_VEC.with_label_values(&["bytes"]).inc();
+                    return ApplyResult::Yield;
+                }
                 if self.written {
+                    APPLY_YIELD_COUNTER_VEC
+                        .with_label_values(&["messages"])
+                        .inc();
                     return ApplyResult::Yield;
                 }
                 self.written = true;
@@ -848,7 +1598,18 @@ impl ApplyDelegate {
 
             return self.process_raft_cmd(apply_ctx, index, term, cmd);
         }
-        // TOOD(cdc): should we observe empty cmd, aka leader change?
+
+        // An empty entry is either a leader's post-election no-op or a
+        // read-index probe proposed while transferring leadership. Neither
+        // carries a `RaftCmdRequest`, so it never reaches `process_raft_cmd`
+        // and its `on_apply_cmd` call - tell the observer directly so a CDC
+        // resolver watching this region can still advance its resolved-ts
+        // past this index.
+        for observe_cmd in self.observe_cmds.values() {
+            apply_ctx
+                .host
+                .on_empty_entry(observe_cmd.id, self.region_id(), index, term);
+        }
 
         self.apply_state.set_applied_index(index);
         self.applied_index_term = term;
@@ -889,7 +1650,7 @@ impl ApplyDelegate {
             }
             ApplyResult::Res(mut res) => {
                 if let ExecResult::ChangePeer(ref mut cp) = res {
-                    cp.conf_change = conf_change;
+                    cp.conf_change = ConfChangeRequest::V1(conf_change);
                 } else {
                     panic!(
                         "{} unexpected result {:?} for conf change {:?} at {}",
@@ -898,7 +1659,48 @@ impl ApplyDelegate {
                 }
                 ApplyResult::Res(res)
             }
-            ApplyResult::Yield | ApplyResult::WaitMergeSource(_) => unreachable!(),
+            ApplyResult::Yield | ApplyResult::WaitMergeSource => unreachable!(),
+        }
+    }
+
+    /// Like `handle_raft_entry_conf_change`, but for the joint-consensus
+    /// `ConfChangeV2` entries raft emits when a proposal changes more than
+    /// one peer, or demotes a voter, at once. The embedded `RaftCmdRequest`
+    /// carries a `ChangePeerV2Request` rather than a `ChangePeerRequest`;
+    /// `exec_change_peer` tells the two apart and applies every single
+    /// change atomically.
+    fn handle_raft_entry_conf_change_v2<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
+        &mut self,
+        apply_ctx: &mut ApplyContext<W>,
+        entry: &Entry,
+    ) -> ApplyResult {
+        fail_point!("yield_apply_conf_change_3", self.id() == 3, |_| {
+            ApplyResult::Yield
+        });
+        let index = entry.get_index();
+        let term = entry.get_term();
+        let conf_change: ConfChangeV2 = util::parse_data_at(entry.get_data(), index, &self.tag);
+        let cmd = util::parse_data_at(conf_change.get_context(), index, &self.tag);
+        match self.process_raft_cmd(apply_ctx, index, term, cmd) {
+            ApplyResult::None => {
+                // If failed, tell Raft that the whole joint change was
+                // aborted. There's nothing to finalize, so report the
+                // `ChangePeer` default, whose `conf_change` is an empty
+                // `ConfChangeRequest::V2`.
+                ApplyResult::Res(ExecResult::ChangePeer(Default::default()))
+            }
+            ApplyResult::Res(mut res) => {
+                if let ExecResult::ChangePeer(ref mut cp) = res {
+                    cp.conf_change = ConfChangeRequest::V2(conf_change);
+                } else {
+                    panic!(
+                        "{} unexpected result {:?} for conf change v2 {:?} at {}",
+                        self.tag, res, conf_change, index
+                    );
+                }
+                ApplyResult::Res(res)
+            }
+            ApplyResult::Yield | ApplyResult::WaitMergeSource => unreachable!(),
         }
     }
 
@@ -907,12 +1709,17 @@ impl ApplyDelegate {
         index: u64,
         term: u64,
         is_conf_change: bool,
-    ) -> Option<Callback<RocksEngine>> {
+        wait_nanos: u64,
+    ) -> Option<(Callback<RocksEngine>, ApplyDetail)> {
         let (region_id, peer_id) = (self.region_id(), self.id());
+        let detail = ApplyDetail {
+            wait_nanos,
+            ..Default::default()
+        };
         if is_conf_change {
             if let Some(mut cmd) = self.pending_cmds.take_conf_change() {
                 if cmd.index == index && cmd.term == term {
-                    return Some(cmd.cb.take().unwrap());
+                    return Some((cmd.cb.take().unwrap(), detail));
                 } else {
                     notify_stale_command(region_id, peer_id, self.term, cmd);
                 }
@@ -922,7 +1729,7 @@ impl ApplyDelegate {
         while let Some(mut head) = self.pending_cmds.pop_normal(index, term) {
             if head.term == term {
                 if head.index == index {
-                    return Some(head.cb.take().unwrap());
+                    return Some((head.cb.take().unwrap(), detail));
                 } else {
                     panic!(
                         "{} unexpected callback at term {}, found index {}, expected {}",
@@ -955,13 +1762,41 @@ impl ApplyDelegate {
         // Set sync log hint if the cmd requires so.
         apply_ctx.sync_log_hint |= should_sync_log(&cmd);
 
-        let is_conf_change = get_change_peer_cmd(&cmd).is_some();
+        let is_conf_change =
+            get_change_peer_cmd(&cmd).is_some() || get_change_peer_v2_cmd(&cmd).is_some();
         apply_ctx.host.pre_apply(&self.region, &cmd);
         let (mut resp, exec_result) = self.apply_raft_cmd(apply_ctx, index, term, &cmd);
-        if let ApplyResult::WaitMergeSource(_) = exec_result {
+        if let ApplyResult::WaitMergeSource = exec_result {
             return exec_result;
         }
 
+        if !self.pending_ingest_ssts.is_empty() {
+            // Cheap no-op when empty; any command that bumped this region's
+            // epoch (split/merge/conf-change) may have unblocked a
+            // previously-deferred `IngestSst`. Ingesting here is a
+            // best-effort side effect of this command rather than its own
+            // `ExecResult` - the apply protocol carries one exec result per
+            // command, and a retried ingest isn't the result this command
+            // was proposed for.
+            let mut retried_ssts = Vec::new();
+            self.retry_pending_ingest_ssts(
+                &apply_ctx.importer,
+                &apply_ctx.engine,
+                &apply_ctx.chunk_store,
+                &apply_ctx.ingest_quarantine,
+                apply_ctx.max_ingest_attempts,
+                &mut retried_ssts,
+            );
+            if !retried_ssts.is_empty() {
+                info!(
+                    "retried deferred ingests after epoch change";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.id(),
+                    "count" => retried_ssts.len(),
+                );
+            }
+        }
+
         debug!(
             "applied command";
             "region_id" => self.region_id(),
@@ -972,15 +1807,58 @@ impl ApplyDelegate {
         // TODO: if we have exec_result, maybe we should return this callback too. Outer
         // store will call it after handing exec result.
         cmd_resp::bind_term(&mut resp, self.term);
-        let cmd_cb = self.find_cb(index, term, is_conf_change);
-        if let Some(observe_cmd) = self.observe_cmd.as_ref() {
-            let cmd = Cmd::new(index, cmd, resp.clone());
+        let (cmd_cb, cmd_detail) = match self.find_cb(
+            index,
+            term,
+            is_conf_change,
+            apply_ctx.timing.apply_batch_wait_nanos,
+        ) {
+            Some((cb, detail)) => (Some(cb), detail),
+            None => (None, ApplyDetail::default()),
+        };
+        // Append this entry to the region's applied-entry accumulator
+        // before fanning it out, so every observer notified below is
+        // watching a region whose accumulator root already reflects this
+        // entry - a consumer that queries `entry_log_root` right after
+        // observing this command won't see a root one entry behind.
+        let cmd_hash = openssl::sha::sha256(&cmd.write_to_bytes().unwrap_or_default());
+        apply_ctx
+            .entry_log
+            .append(self.region_id(), index, term, cmd_hash);
+
+        // Fan the same command out to every enabled observer - CDC,
+        // resolved-ts, and incremental backup may all be watching this
+        // region at once.
+        for observe_cmd in self.observe_cmds.values() {
+            let observe_id = observe_cmd.id;
+            // Membership and key-range changes don't go through the regular
+            // Put/Delete observer path below, but a resolver watching this
+            // region still needs to know about them to advance its
+            // resolved-ts or re-initialize its cache.
+            if let ApplyResult::Res(ref res) = exec_result {
+                if matches!(
+                    res,
+                    ExecResult::SplitRegion { .. }
+                        | ExecResult::PrepareMerge { .. }
+                        | ExecResult::CommitMerge { .. }
+                        | ExecResult::ChangePeer(..)
+                ) {
+                    apply_ctx
+                        .host
+                        .on_apply_admin(observe_id, self.region_id(), res);
+                }
+            }
+            let observed_cmd = Cmd::new(index, cmd.clone(), resp.clone());
             apply_ctx
                 .host
-                .on_apply_cmd(observe_cmd.id, self.region_id(), cmd);
+                .on_apply_cmd(observe_id, self.region_id(), observed_cmd);
         }
 
-        apply_ctx.cbs.last_mut().unwrap().push(cmd_cb, resp);
+        apply_ctx
+            .cbs
+            .last_mut()
+            .unwrap()
+            .push(cmd_cb, resp, cmd_detail);
 
         exec_result
     }
@@ -1030,7 +1908,7 @@ impl ApplyDelegate {
                 (cmd_resp::new_error(e), ApplyResult::None)
             }
         };
-        if let ApplyResult::WaitMergeSource(_) = exec_result {
+        if let ApplyResult::WaitMergeSource = exec_result {
             return (resp, exec_result);
         }
 
@@ -1054,6 +1932,13 @@ impl ApplyDelegate {
                     self.region = derived.clone();
                     self.metrics.size_diff_hint = 0;
                     self.metrics.delete_keys_hint = 0;
+                    // This delegate's key range just shrank; drop the digest
+                    // and tree and mark them incomplete so the next
+                    // `ComputeHash` rebuilds them from the engine.
+                    self.consistency_digest.reset();
+                    self.merkle_tree(&ctx.region_merkle).lock().unwrap().reset();
+                    self.digest_is_complete = false;
+                    self.merkle_is_complete = false;
                 }
                 ExecResult::PrepareMerge { ref region, .. } => {
                     self.region = region.clone();
@@ -1062,6 +1947,13 @@ impl ApplyDelegate {
                 ExecResult::CommitMerge { ref region, .. } => {
                     self.region = region.clone();
                     self.last_merge_version = region.get_region_epoch().get_version();
+                    // The source region's key range was just folded in; the
+                    // next `ComputeHash` rebuilds the digest and tree over the
+                    // combined keyspace.
+                    self.consistency_digest.reset();
+                    self.merkle_tree(&ctx.region_merkle).lock().unwrap().reset();
+                    self.digest_is_complete = false;
+                    self.merkle_is_complete = false;
                 }
                 ExecResult::RollbackMerge { ref region, .. } => {
                     self.region = region.clone();
@@ -1085,6 +1977,11 @@ impl ApplyDelegate {
         if let Some(cmd) = self.pending_cmds.conf_change.take() {
             notify_region_removed(self.region.get_id(), self.id, cmd);
         }
+        // This region's data is gone for good, so every SST it ever chunked
+        // can give up its chunk references.
+        for sst in self.chunked_ssts.drain(..) {
+            apply_ctx.chunk_store.release_sst(sst.get_uuid());
+        }
     }
 
     fn clear_all_commands_as_stale(&mut self) {
@@ -1139,7 +2036,9 @@ impl ApplyDelegate {
         }
 
         let (mut response, exec_result) = match cmd_type {
-            AdminCmdType::ChangePeer => self.exec_change_peer(ctx, request),
+            AdminCmdType::ChangePeer | AdminCmdType::ChangePeerV2 => {
+                self.exec_change_peer(ctx, request)
+            }
             AdminCmdType::Split => self.exec_split(ctx, request),
             AdminCmdType::BatchSplit => self.exec_batch_split(ctx, request),
             AdminCmdType::CompactLog => self.exec_compact_log(ctx, request),
@@ -1184,14 +2083,34 @@ impl ApplyDelegate {
         for req in requests {
             let cmd_type = req.get_cmd_type();
             let mut resp = match cmd_type {
-                CmdType::Put => self.handle_put(ctx.kv_wb_mut(), req),
-                CmdType::Delete => self.handle_delete(ctx.kv_wb_mut(), req),
-                CmdType::DeleteRange => {
-                    self.handle_delete_range(&ctx.engine, req, &mut ranges, ctx.use_delete_range)
+                CmdType::Put => {
+                    let region_merkle = ctx.region_merkle.clone();
+                    let engine = ctx.engine.clone();
+                    self.handle_put(&engine, ctx.kv_wb_mut(), &region_merkle, req)
                 }
-                CmdType::IngestSst => {
-                    self.handle_ingest_sst(&ctx.importer, &ctx.engine, req, &mut ssts)
+                CmdType::Delete => {
+                    let region_merkle = ctx.region_merkle.clone();
+                    let engine = ctx.engine.clone();
+                    self.handle_delete(&engine, ctx.kv_wb_mut(), &region_merkle, req)
                 }
+                CmdType::DeleteRange => self.handle_delete_range(
+                    &ctx.engine,
+                    &ctx.chunk_store,
+                    req,
+                    &mut ranges,
+                    ctx.use_delete_range,
+                ),
+                CmdType::IngestSst => self.handle_ingest_sst(
+                    &ctx.importer,
+                    &ctx.engine,
+                    &ctx.chunk_store,
+                    &ctx.ingest_quarantine,
+                    ctx.max_ingest_attempts,
+                    ctx.exec_ctx.as_ref().unwrap().index,
+                    ctx.exec_ctx.as_ref().unwrap().term,
+                    req,
+                    &mut ssts,
+                ),
                 // Readonly commands are handled in raftstore directly.
                 // Don't panic here in case there are old entries need to be applied.
                 // It's also safe to skip them here, because a restart must have happened,
@@ -1237,7 +2156,26 @@ impl ApplyDelegate {
 
 // Write commands related.
 impl ApplyDelegate {
-    fn handle_put<W: WriteBatch>(&mut self, wb: &mut W, req: &Request) -> Result<Response> {
+    /// This delegate's handle into its region's shared [`RegionMerkle`],
+    /// fetched from `manager` by region id rather than cached on
+    /// `ApplyDelegate` so any other holder of the same [`RegionMerkleManager`]
+    /// (an operator endpoint, a repair job) sees the identical tree.
+    ///
+    /// [`RegionMerkle`]: region_merkle::RegionMerkle
+    fn merkle_tree(
+        &self,
+        manager: &RegionMerkleManager,
+    ) -> Arc<Mutex<region_merkle::RegionMerkle>> {
+        manager.get_or_insert(self.region_id())
+    }
+
+    fn handle_put<W: WriteBatch>(
+        &mut self,
+        engine: &RocksEngine,
+        wb: &mut W,
+        region_merkle: &RegionMerkleManager,
+        req: &Request,
+    ) -> Result<Response> {
         let (key, value) = (req.get_put().get_key(), req.get_put().get_value());
         // region key range has no data prefix, so we must use origin key to check.
         util::check_key_in_region(key, &self.region)?;
@@ -1253,6 +2191,14 @@ impl ApplyDelegate {
                 self.metrics.lock_cf_written_bytes += key.len() as u64;
                 self.metrics.lock_cf_written_bytes += value.len() as u64;
             }
+            // Read the key's current value so the digest can un-fold it
+            // before folding in the new one, keeping the digest state-based
+            // rather than accumulating every value a key has ever held.
+            let old_value = engine
+                .get_value_cf(cf, &key)
+                .map_err(|e| box_err!("failed to read old value of {} for cf {}: {:?}", hex::encode_upper(&key), cf, e))?;
+            self.consistency_digest.fold_put(cf, &key, value, old_value.as_deref());
+            self.merkle_tree(region_merkle).lock().unwrap().upsert(cf, &key, value);
             // TODO: check whether cf exists or not.
             wb.put_cf(cf, &key, value).unwrap_or_else(|e| {
                 panic!(
@@ -1265,6 +2211,14 @@ impl ApplyDelegate {
                 )
             });
         } else {
+            let old_value = engine
+                .get_value(&key)
+                .map_err(|e| box_err!("failed to read old value of {}: {:?}", hex::encode_upper(&key), e))?;
+            self.consistency_digest.fold_put(CF_DEFAULT, &key, value, old_value.as_deref());
+            self.merkle_tree(region_merkle)
+                .lock()
+                .unwrap()
+                .upsert(CF_DEFAULT, &key, value);
             wb.put(&key, value).unwrap_or_else(|e| {
                 panic!(
                     "{} failed to write ({}, {}): {:?}",
@@ -1278,7 +2232,13 @@ impl ApplyDelegate {
         Ok(resp)
     }
 
-    fn handle_delete<W: WriteBatch>(&mut self, wb: &mut W, req: &Request) -> Result<Response> {
+    fn handle_delete<W: WriteBatch>(
+        &mut self,
+        engine: &RocksEngine,
+        wb: &mut W,
+        region_merkle: &RegionMerkleManager,
+        req: &Request,
+    ) -> Result<Response> {
         let key = req.get_delete().get_key();
         // region key range has no data prefix, so we must use origin key to check.
         util::check_key_in_region(key, &self.region)?;
@@ -1290,6 +2250,9 @@ impl ApplyDelegate {
         if !req.get_delete().get_cf().is_empty() {
             let cf = req.get_delete().get_cf();
             // TODO: check whether cf exists or not.
+            let old_value = engine
+                .get_value_cf(cf, &key)
+                .map_err(|e| box_err!("failed to read old value of {} for cf {}: {:?}", hex::encode_upper(&key), cf, e))?;
             wb.delete_cf(cf, &key).unwrap_or_else(|e| {
                 panic!(
                     "{} failed to delete {}: {}",
@@ -1298,6 +2261,8 @@ impl ApplyDelegate {
                     e
                 )
             });
+            self.consistency_digest.fold_delete(cf, &key, old_value.as_deref());
+            self.merkle_tree(region_merkle).lock().unwrap().remove(cf, &key);
 
             if cf == CF_LOCK {
                 // delete is a kind of write for RocksDB.
@@ -1306,6 +2271,9 @@ impl ApplyDelegate {
                 self.metrics.delete_keys_hint += 1;
             }
         } else {
+            let old_value = engine
+                .get_value(&key)
+                .map_err(|e| box_err!("failed to read old value of {}: {:?}", hex::encode_upper(&key), e))?;
             wb.delete(&key).unwrap_or_else(|e| {
                 panic!(
                     "{} failed to delete {}: {}",
@@ -1314,6 +2282,11 @@ impl ApplyDelegate {
                     e
                 )
             });
+            self.consistency_digest.fold_delete(CF_DEFAULT, &key, old_value.as_deref());
+            self.merkle_tree(region_merkle)
+                .lock()
+                .unwrap()
+                .remove(CF_DEFAULT, &key);
             self.metrics.delete_keys_hint += 1;
         }
 
@@ -1323,6 +2296,7 @@ impl ApplyDelegate {
     fn handle_delete_range(
         &mut self,
         engine: &RocksEngine,
+        chunk_store: &Arc<content_chunking::ChunkStore>,
         req: &Request,
         ranges: &mut Vec<Range>,
         use_delete_range: bool,
@@ -1388,6 +2362,23 @@ impl ApplyDelegate {
         // TODO: Should this be executed when `notify_only` is set?
         ranges.push(Range::new(cf.to_owned(), start_key, end_key));
 
+        if !notify_only {
+            // Any chunked SST whose ingested range and cf fall entirely
+            // within what was just deleted no longer has live data backing
+            // it, so its chunk references can be released. A partially
+            // covered SST is left alone - some of its data may still be live.
+            let (released, remaining): (Vec<_>, Vec<_>) =
+                self.chunked_ssts.drain(..).partition(|sst| {
+                    let sst_start = keys::data_key(sst.get_range().get_start());
+                    let sst_end = keys::data_end_key(sst.get_range().get_end());
+                    sst.get_cf_name() == cf && sst_start >= start_key && sst_end <= end_key
+                });
+            self.chunked_ssts = remaining;
+            for sst in &released {
+                chunk_store.release_sst(sst.get_uuid());
+            }
+        }
+
         Ok(resp)
     }
 
@@ -1395,12 +2386,38 @@ impl ApplyDelegate {
         &mut self,
         importer: &Arc<SSTImporter>,
         engine: &RocksEngine,
+        chunk_store: &Arc<content_chunking::ChunkStore>,
+        ingest_quarantine: &Arc<ingest_quarantine::IngestQuarantine>,
+        max_ingest_attempts: u32,
+        index: u64,
+        term: u64,
         req: &Request,
         ssts: &mut Vec<SstMeta>,
     ) -> Result<Response> {
         let sst = req.get_ingest_sst().get_sst();
 
-        if let Err(e) = check_sst_for_ingestion(sst, &self.region) {
+        if let Err(e) = check_sst_for_ingestion(sst, &self.region, importer) {
+            if let Error::EpochNotMatch(..) = e {
+                // Likely transient: a concurrent split/merge/conf-change
+                // moved this region's epoch on while the ingest was in
+                // flight. Defer instead of deleting - `retry_pending_ingest_ssts`
+                // re-checks it every time this delegate's epoch advances.
+                info!(
+                    "deferring ingest on stale epoch";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.id(),
+                    "sst" => ?sst,
+                    "region" => ?&self.region,
+                    "err" => ?e
+                );
+                self.pending_ingest_ssts.push(PendingIngestSst {
+                    sst: sst.clone(),
+                    registered_at: Instant::now_coarse(),
+                    index,
+                    term,
+                });
+                return Ok(Response::default());
+            }
             error!(
                  "ingest fail";
                  "region_id" => self.region_id(),
@@ -1414,31 +2431,200 @@ impl ApplyDelegate {
             return Err(e);
         }
 
-        importer.ingest(sst, engine).unwrap_or_else(|e| {
-            // If this failed, it means that the file is corrupted or something
-            // is wrong with the engine, but we can do nothing about that.
-            panic!("{} ingest {:?}: {:?}", self.tag, sst, e);
-        });
-
-        ssts.push(sst.clone());
+        self.ingest_sst(
+            importer,
+            engine,
+            chunk_store,
+            ingest_quarantine,
+            max_ingest_attempts,
+            index,
+            term,
+            sst,
+            ssts,
+        );
         Ok(Response::default())
     }
-}
 
-// Admin commands related.
-impl ApplyDelegate {
-    fn exec_change_peer<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
+    /// Chunks, dedups, and ingests an SST already known to match this
+    /// delegate's current region - shared by `handle_ingest_sst` and
+    /// `retry_pending_ingest_ssts` so a deferred ingest gets identical
+    /// treatment to one that matched on the first try.
+    ///
+    /// A failure at the importer/engine boundary (as opposed to the
+    /// stale-epoch case handled by `handle_ingest_sst`) no longer panics
+    /// the apply loop: it's recorded in `ingest_quarantine` and the command
+    /// is simply omitted from `ssts`, so the rest of this batch - and the
+    /// rest of this region's apply progress - keeps moving. Below
+    /// `max_ingest_attempts`, the entry is re-queued onto
+    /// `pending_ingest_ssts` so `retry_pending_ingest_ssts` gives it another
+    /// attempt on a later poll cycle exactly like a deferred stale-epoch
+    /// ingest; once it's failed that many times it's left in quarantine for
+    /// an operator to inspect via `Builder::quarantined_ingests` instead.
+    fn ingest_sst(
         &mut self,
-        ctx: &mut ApplyContext<W>,
-        request: &AdminRequest,
-    ) -> Result<(AdminResponse, ApplyResult)> {
-        let request = request.get_change_peer();
-        let peer = request.get_peer();
-        let store_id = peer.get_store_id();
-        let change_type = request.get_change_type();
-        let mut region = self.region.clone();
-
-        fail_point!(
+        importer: &Arc<SSTImporter>,
+        engine: &RocksEngine,
+        chunk_store: &Arc<content_chunking::ChunkStore>,
+        ingest_quarantine: &Arc<ingest_quarantine::IngestQuarantine>,
+        max_ingest_attempts: u32,
+        index: u64,
+        term: u64,
+        sst: &SstMeta,
+        ssts: &mut Vec<SstMeta>,
+    ) {
+        // Content-defined chunking lets repeated or overlapping bulk-loads
+        // share bytes instead of each ingest paying full write amplification.
+        // Best-effort: a read failure here must not block the ingest itself.
+        if let Ok(data) = importer.read_sst_bytes(sst) {
+            let written = chunk_store.put_sst(sst.get_uuid().to_vec(), &data);
+            debug!(
+                "chunked ingested sst";
+                "region_id" => self.region_id(),
+                "peer_id" => self.id(),
+                "sst" => ?sst,
+                "new_chunks" => written,
+            );
+            // Tracked so `handle_delete_range`/`destroy` can release this
+            // SST's chunk references once its data is gone; otherwise
+            // `chunk_store`'s ref-counts only ever grow.
+            self.chunked_ssts.push(sst.clone());
+        }
+
+        if let Err(e) = importer.ingest(sst, engine) {
+            let attempts =
+                ingest_quarantine.record_failure(self.region_id(), index, term, sst, format!("{:?}", e));
+            APPLY_INGEST_QUARANTINE_COUNTER_VEC
+                .with_label_values(&["failure"])
+                .inc();
+            if attempts >= max_ingest_attempts {
+                error!(
+                    "ingest sst exhausted retries, quarantining";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.id(),
+                    "sst" => ?sst,
+                    "attempts" => attempts,
+                    "err" => ?e,
+                );
+            } else {
+                warn!(
+                    "ingest sst failed, will retry";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.id(),
+                    "sst" => ?sst,
+                    "attempts" => attempts,
+                    "err" => ?e,
+                );
+                self.pending_ingest_ssts.push(PendingIngestSst {
+                    sst: sst.clone(),
+                    registered_at: Instant::now_coarse(),
+                    index,
+                    term,
+                });
+            }
+            return;
+        }
+
+        ingest_quarantine.remove(sst.get_uuid());
+        ssts.push(sst.clone());
+    }
+
+    /// Re-validates every SST this delegate deferred from
+    /// `handle_ingest_sst` against its current (post-epoch-bump) region.
+    /// Call after any admin command that advances the region epoch -
+    /// `exec_batch_split`, `exec_commit_merge`, `exec_change_peer` - so a
+    /// deferred ingest is retried as soon as it could plausibly succeed,
+    /// rather than waiting for the next unrelated `IngestSst` command.
+    /// Entries still unsatisfiable past `PENDING_INGEST_SST_TTL` are given
+    /// up on and deleted so importer scratch space doesn't leak forever.
+    fn retry_pending_ingest_ssts(
+        &mut self,
+        importer: &Arc<SSTImporter>,
+        engine: &RocksEngine,
+        chunk_store: &Arc<content_chunking::ChunkStore>,
+        ingest_quarantine: &Arc<ingest_quarantine::IngestQuarantine>,
+        max_ingest_attempts: u32,
+        ssts: &mut Vec<SstMeta>,
+    ) {
+        if self.pending_ingest_ssts.is_empty() {
+            return;
+        }
+        let region = self.region.clone();
+        let pending = std::mem::take(&mut self.pending_ingest_ssts);
+        for p in pending {
+            match check_sst_for_ingestion(&p.sst, &region, importer) {
+                Ok(()) => {
+                    info!(
+                        "retrying deferred ingest";
+                        "region_id" => self.region_id(),
+                        "peer_id" => self.id(),
+                        "sst" => ?p.sst,
+                    );
+                    self.ingest_sst(
+                        importer,
+                        engine,
+                        chunk_store,
+                        ingest_quarantine,
+                        max_ingest_attempts,
+                        p.index,
+                        p.term,
+                        &p.sst,
+                        ssts,
+                    );
+                }
+                Err(e) if p.registered_at.elapsed() < PENDING_INGEST_SST_TTL => {
+                    debug!(
+                        "deferred ingest still not valid, keeping";
+                        "region_id" => self.region_id(),
+                        "peer_id" => self.id(),
+                        "sst" => ?p.sst,
+                        "err" => ?e,
+                    );
+                    self.pending_ingest_ssts.push(p);
+                }
+                Err(e) => {
+                    warn!(
+                        "deferred ingest expired, giving up";
+                        "region_id" => self.region_id(),
+                        "peer_id" => self.id(),
+                        "sst" => ?p.sst,
+                        "err" => ?e,
+                    );
+                    let _ = importer.delete(&p.sst);
+                }
+            }
+        }
+    }
+}
+
+// Admin commands related.
+impl ApplyDelegate {
+    fn exec_change_peer<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
+        &mut self,
+        ctx: &mut ApplyContext<W>,
+        request: &AdminRequest,
+    ) -> Result<(AdminResponse, ApplyResult)> {
+        let is_v2 = request.has_change_peer_v2();
+        let changes: Vec<PeerChange> = if is_v2 {
+            request
+                .get_change_peer_v2()
+                .get_changes()
+                .iter()
+                .map(|c| PeerChange {
+                    change_type: c.get_change_type(),
+                    peer: c.get_peer().clone(),
+                })
+                .collect()
+        } else {
+            let request = request.get_change_peer();
+            vec![PeerChange {
+                change_type: request.get_change_type(),
+                peer: request.get_peer().clone(),
+            }]
+        };
+        let kind = ConfChangeKind::confchange_kind(changes.len());
+        let mut region = self.region.clone();
+
+        fail_point!(
             "apply_on_conf_change_1_3_1",
             (self.id == 1 || self.id == 3) && self.region_id() == 1,
             |_| panic!("should not use return")
@@ -1457,7 +2643,8 @@ impl ApplyDelegate {
             "exec ConfChange";
             "region_id" => self.region_id(),
             "peer_id" => self.id(),
-            "type" => util::conf_change_type_str(change_type),
+            "kind" => ?kind,
+            "changes" => ?changes,
             "epoch" => ?region.get_region_epoch(),
         );
 
@@ -1465,7 +2652,99 @@ impl ApplyDelegate {
         let conf_ver = region.get_region_epoch().get_conf_ver() + 1;
         region.mut_region_epoch().set_conf_ver(conf_ver);
 
-        match change_type {
+        match kind {
+            ConfChangeKind::LeaveJoint => {
+                // The peer roles were already updated when we entered the
+                // joint configuration; there's nothing left to change on
+                // `region.peers`, only the joint flag to clear.
+                self.in_joint_state = false;
+                info!(
+                    "leave joint state";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.id(),
+                    "region" => ?&region,
+                );
+            }
+            ConfChangeKind::EnterJoint => {
+                for change in &changes {
+                    self.apply_single_change_peer(&mut region, change)?;
+                }
+                self.in_joint_state = true;
+            }
+            ConfChangeKind::Simple => {
+                self.apply_single_change_peer(&mut region, &changes[0])?;
+            }
+        }
+
+        let state = if self.pending_remove {
+            PeerState::Tombstone
+        } else {
+            PeerState::Normal
+        };
+        if let Err(e) = write_peer_state(ctx.kv_wb_mut(), &region, state, None) {
+            panic!("{} failed to update region state: {:?}", self.tag, e);
+        }
+
+        let mut resp = AdminResponse::default();
+        if is_v2 {
+            resp.mut_change_peer_v2().set_region(region.clone());
+        } else {
+            resp.mut_change_peer().set_region(region.clone());
+        }
+
+        // Only a single add covers the common scaling/rebalancing case; a
+        // joint change mixes in removes/demotions that make "just copy what
+        // we have" unsafe to reason about here, so those always fall back to
+        // a full snapshot.
+        let is_add = kind == ConfChangeKind::Simple
+            && matches!(
+                changes[0].change_type,
+                ConfChangeType::AddNode | ConfChangeType::AddLearnerNode
+            );
+        let fast_add_peer = if is_add && self.data_is_durable() {
+            // A peer added through the fast path doesn't need the snapshot
+            // generator to also scan this region, so mark this specific peer
+            // initialized up front; this is cleared again
+            // (`Msg::SetRegionCacheInit`) once the fast-bootstrapped peer has
+            // actually caught up, since a later genuinely-needed snapshot for
+            // it must not be filtered. Only this peer is marked - a sibling
+            // peer of the same region that's genuinely behind still needs its
+            // own snapshot.
+            ctx.cached_region_info.set_inited_or_fallback(
+                self.region_id(),
+                changes[0].peer.get_id(),
+                true,
+            );
+            Some(FastAddPeer {
+                region: region.clone(),
+                apply_state: self.apply_state.clone(),
+            })
+        } else {
+            None
+        };
+
+        Ok((
+            resp,
+            ApplyResult::Res(ExecResult::ChangePeer(ChangePeer {
+                index: ctx.exec_ctx.as_ref().unwrap().index,
+                conf_change: Default::default(),
+                changes,
+                region,
+                fast_add_peer,
+            })),
+        ))
+    }
+
+    /// Applies a single `AddNode`/`RemoveNode`/`AddLearnerNode` change to
+    /// `region`'s peer list. Shared by the legacy single-change path and the
+    /// joint-consensus path, which calls this once per `ConfChangeSingle` in
+    /// the batch after bumping `conf_version` exactly once for the whole
+    /// command.
+    fn apply_single_change_peer(&mut self, region: &mut Region, change: &PeerChange) -> Result<()> {
+        let peer = &change.peer;
+        let store_id = peer.get_store_id();
+
+        match change.change_type {
             ConfChangeType::AddNode => {
                 let add_ndoe_fp = || {
                     fail_point!(
@@ -1481,7 +2760,7 @@ impl ApplyDelegate {
                     .inc();
 
                 let mut exists = false;
-                if let Some(p) = util::find_peer_mut(&mut region, store_id) {
+                if let Some(p) = util::find_peer_mut(region, store_id) {
                     exists = true;
                     if !p.get_is_learner() || p.get_id() != peer.get_id() {
                         error!(
@@ -1513,7 +2792,7 @@ impl ApplyDelegate {
                     "region_id" => self.region_id(),
                     "peer_id" => self.id(),
                     "peer" => ?peer,
-                    "region" => ?&self.region
+                    "region" => ?region
                 );
             }
             ConfChangeType::RemoveNode => {
@@ -1521,7 +2800,7 @@ impl ApplyDelegate {
                     .with_label_values(&["remove_peer", "all"])
                     .inc();
 
-                if let Some(p) = util::remove_peer(&mut region, store_id) {
+                if let Some(p) = util::remove_peer(region, store_id) {
                     // Considering `is_learner` flag in `Peer` here is by design.
                     if &p != peer {
                         error!(
@@ -1566,7 +2845,7 @@ impl ApplyDelegate {
                     "region_id" => self.region_id(),
                     "peer_id" => self.id(),
                     "peer" => ?peer,
-                    "region" => ?&self.region
+                    "region" => ?region
                 );
             }
             ConfChangeType::AddLearnerNode => {
@@ -1574,21 +2853,28 @@ impl ApplyDelegate {
                     .with_label_values(&["add_learner", "all"])
                     .inc();
 
-                if util::find_peer(&region, store_id).is_some() {
-                    error!(
-                        "can't add duplicated learner";
-                        "region_id" => self.region_id(),
-                        "peer_id" => self.id(),
-                        "peer" => ?peer,
-                        "region" => ?&self.region
-                    );
-                    return Err(box_err!(
-                        "can't add duplicated learner {:?} to region {:?}",
-                        peer,
-                        self.region
-                    ));
+                if let Some(p) = util::find_peer_mut(region, store_id) {
+                    if p.get_id() == peer.get_id() && !p.get_is_learner() {
+                        // Demote an existing voter to a learner, e.g. as one
+                        // half of a joint "demote + add" pair.
+                        p.set_is_learner(true);
+                    } else {
+                        error!(
+                            "can't add duplicated learner";
+                            "region_id" => self.region_id(),
+                            "peer_id" => self.id(),
+                            "peer" => ?peer,
+                            "region" => ?&self.region
+                        );
+                        return Err(box_err!(
+                            "can't add duplicated learner {:?} to region {:?}",
+                            peer,
+                            self.region
+                        ));
+                    }
+                } else {
+                    region.mut_peers().push(peer.clone());
                 }
-                region.mut_peers().push(peer.clone());
 
                 PEER_ADMIN_CMD_COUNTER_VEC
                     .with_label_values(&["add_learner", "success"])
@@ -1598,32 +2884,11 @@ impl ApplyDelegate {
                     "region_id" => self.region_id(),
                     "peer_id" => self.id(),
                     "peer" => ?peer,
-                    "region" => ?&self.region,
+                    "region" => ?region,
                 );
             }
         }
-
-        let state = if self.pending_remove {
-            PeerState::Tombstone
-        } else {
-            PeerState::Normal
-        };
-        if let Err(e) = write_peer_state(ctx.kv_wb_mut(), &region, state, None) {
-            panic!("{} failed to update region state: {:?}", self.tag, e);
-        }
-
-        let mut resp = AdminResponse::default();
-        resp.mut_change_peer().set_region(region.clone());
-
-        Ok((
-            resp,
-            ApplyResult::Res(ExecResult::ChangePeer(ChangePeer {
-                index: ctx.exec_ctx.as_ref().unwrap().index,
-                conf_change: Default::default(),
-                peer: peer.clone(),
-                region,
-            })),
-        ))
+        Ok(())
     }
 
     fn exec_split<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
@@ -1804,6 +3069,12 @@ impl ApplyDelegate {
                 self.tag, merging_state, region, e
             )
         });
+        // The source region is now finalizing a merge: from here on new
+        // writes are rejected with a clean, retriable response instead of
+        // surfacing as epoch errors. `Downgrading` is the only legal step
+        // from `Writable` towards `Merging`.
+        self.set_role_state_gracefully(RoleState::Downgrading);
+        self.set_role_state_gracefully(RoleState::Merging);
         fail_point!("apply_after_prepare_merge");
         PEER_ADMIN_CMD_COUNTER.prepare_merge.success.inc();
 
@@ -1824,7 +3095,7 @@ impl ApplyDelegate {
     // 3.   if the source peer has already executed the corresponding `on_ready_prepare_merge`, set pending_remove and jump to step 6
     // 4.   ... (raft append and apply logs)
     // 5.   `on_ready_prepare_merge` in source peer fsm and set pending_remove (means source region has finished applying all logs)
-    // 6.   `logs_up_to_date_for_merge` in source apply fsm (destroy its apply fsm and send Noop to trigger the target apply fsm)
+    // 6.   `logs_up_to_date_for_merge` in source apply fsm (destroy its apply fsm and send `Msg::MergeSourceReady` to resume the target apply fsm)
     // 7.   resume `exec_commit_merge` in target apply fsm
     // 8.   `on_ready_commit_merge` in target peer fsm and send `MergeResult` to source peer fsm
     // 9.   `on_merge_result` in source peer fsm (destroy itself)
@@ -1867,19 +3138,26 @@ impl ApplyDelegate {
                 "source_region_id" => source_region_id
             );
             fail_point!("before_handle_catch_up_logs_for_merge");
-            // Sends message to the source peer fsm and pause `exec_commit_merge` process
-            let logs_up_to_date = Arc::new(AtomicU64::new(0));
+            // Sends message to the source peer fsm and pause `exec_commit_merge` process.
+            // The source peer's apply fsm will send `Msg::MergeSourceReady` back to our
+            // own mailbox once it has applied to the required index, which resumes us.
             let msg = SignificantMsg::CatchUpLogs(CatchUpLogs {
                 target_region_id: self.region_id(),
                 merge: merge.to_owned(),
-                logs_up_to_date: logs_up_to_date.clone(),
             });
             ctx.notifier
                 .notify(source_region_id, PeerMsg::SignificantMsg(msg));
-            return Ok((
-                AdminResponse::default(),
-                ApplyResult::WaitMergeSource(logs_up_to_date),
-            ));
+            // Remember this wait so a lost notification, a source fsm that
+            // doesn't exist on this store yet, or a restart before the
+            // source catches up doesn't stall the merge forever - a later
+            // call to `ApplyRouter::retry_pending_commit_merges` re-sends
+            // `CatchUpLogs` until the source reports ready.
+            ctx.router.register_pending_commit_merge(
+                source_region_id,
+                self.region_id(),
+                merge.to_owned(),
+            );
+            return Ok((AdminResponse::default(), ApplyResult::WaitMergeSource));
         }
 
         info!(
@@ -1991,6 +3269,8 @@ impl ApplyDelegate {
                 self.tag, rollback, e
             )
         });
+        // The merge is abandoned: go back to accepting writes normally.
+        self.set_role_state_gracefully(RoleState::Writable);
 
         PEER_ADMIN_CMD_COUNTER.rollback_merge.success.inc();
         let resp = AdminResponse::default();
@@ -2064,25 +3344,80 @@ impl ApplyDelegate {
     }
 
     fn exec_compute_hash<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
-        &self,
+        &mut self,
         ctx: &ApplyContext<W>,
         _: &AdminRequest,
     ) -> Result<(AdminResponse, ApplyResult)> {
+        if !self.digest_is_complete {
+            self.rebuild_consistency_digest(&ctx.engine)?;
+        }
+        if !self.merkle_is_complete {
+            self.rebuild_region_merkle(&ctx.engine, &ctx.region_merkle)?;
+        }
         let resp = AdminResponse::default();
         Ok((
             resp,
             ApplyResult::Res(ExecResult::ComputeHash {
                 region: self.region.clone(),
                 index: ctx.exec_ctx.as_ref().unwrap().index,
-                // This snapshot may be held for a long time, which may cause too many
-                // open files in rocksdb.
-                // TODO: figure out another way to do consistency check without snapshot
-                // or short life snapshot.
-                snap: ctx.engine.snapshot(),
+                // Captured from the running ConsistencyDigest instead of a
+                // RocksDB snapshot, so this no longer holds a long-lived
+                // handle open across the whole consistency check.
+                digest: self.consistency_digest.0,
             }),
         ))
     }
 
+    /// Rebuilds `consistency_digest` from this region's actual content
+    /// instead of trusting the incremental XOR fold, which only covers
+    /// entries applied since this delegate was (re)created. Equivalent to
+    /// the long-lived-snapshot approach `ConsistencyDigest` replaced, but
+    /// paid for once per restart/snapshot-install rather than on every
+    /// `ComputeHash`.
+    fn rebuild_consistency_digest(&mut self, engine: &RocksEngine) -> Result<()> {
+        let mut digest = ConsistencyDigest::default();
+        let start_key = keys::enc_start_key(&self.region);
+        let end_key = keys::enc_end_key(&self.region);
+        for cf in ALL_CFS {
+            engine
+                .scan_cf(cf, &start_key, &end_key, false, |key, value| {
+                    digest.fold_put(cf, key, value, None);
+                    Ok(true)
+                })
+                .map_err(|e| box_err!("failed to rebuild consistency digest for cf {}: {:?}", cf, e))?;
+        }
+        self.consistency_digest = digest;
+        self.digest_is_complete = true;
+        Ok(())
+    }
+
+    /// Rebuilds this region's [`region_merkle::RegionMerkle`] from its
+    /// actual current content, the same way `rebuild_consistency_digest`
+    /// rebuilds the digest. Like that rebuild, this one is exact: the tree
+    /// only ever stores the latest `(cf, key)` hash, never stale or deleted
+    /// contributions, so reconstructing it from a live scan yields
+    /// the same tree a peer that kept running and never reset would have.
+    fn rebuild_region_merkle(
+        &mut self,
+        engine: &RocksEngine,
+        region_merkle: &RegionMerkleManager,
+    ) -> Result<()> {
+        let tree = self.merkle_tree(region_merkle);
+        tree.lock().unwrap().reset();
+        let start_key = keys::enc_start_key(&self.region);
+        let end_key = keys::enc_end_key(&self.region);
+        for cf in ALL_CFS {
+            engine
+                .scan_cf(cf, &start_key, &end_key, false, |key, value| {
+                    tree.lock().unwrap().upsert(cf, key, value);
+                    Ok(true)
+                })
+                .map_err(|e| box_err!("failed to rebuild region merkle for cf {}: {:?}", cf, e))?;
+        }
+        self.merkle_is_complete = true;
+        Ok(())
+    }
+
     fn exec_verify_hash<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
         &self,
         _: &ApplyContext<W>,
@@ -2111,7 +3446,39 @@ pub fn get_change_peer_cmd(msg: &RaftCmdRequest) -> Option<&ChangePeerRequest> {
     Some(req.get_change_peer())
 }
 
-fn check_sst_for_ingestion(sst: &SstMeta, region: &Region) -> Result<()> {
+pub fn get_change_peer_v2_cmd(msg: &RaftCmdRequest) -> Option<&ChangePeerV2Request> {
+    if !msg.has_admin_request() {
+        return None;
+    }
+    let req = msg.get_admin_request();
+    if !req.has_change_peer_v2() {
+        return None;
+    }
+
+    Some(req.get_change_peer_v2())
+}
+
+/// Whether an incoming `MsgSnapshot` for a peer should be dropped because
+/// the peer was already bootstrapped through the `FastAddPeer` fast path.
+///
+/// Called from the peer fsm's raft-message handling, before a snapshot is
+/// handed to raft-rs, with the applied index the fast path already seeded
+/// the peer with and the index the incoming snapshot would install. If the
+/// snapshot wouldn't move the peer any further than the fast path already
+/// did, it's stale and must be filtered - applying it would needlessly
+/// overwrite the peer's data with an equivalent (or older) copy.
+pub fn should_filter_stale_fast_add_snapshot(
+    fast_add_applied_index: u64,
+    snapshot_applied_index: u64,
+) -> bool {
+    snapshot_applied_index <= fast_add_applied_index
+}
+
+fn check_sst_for_ingestion(
+    sst: &SstMeta,
+    region: &Region,
+    importer: &Arc<SSTImporter>,
+) -> Result<()> {
     let uuid = sst.get_uuid();
     if let Err(e) = UuidBuilder::from_slice(uuid) {
         return Err(box_err!("invalid uuid {:?}: {:?}", uuid, e));
@@ -2140,6 +3507,25 @@ fn check_sst_for_ingestion(sst: &SstMeta, region: &Region) -> Result<()> {
     util::check_key_in_region(range.get_start(), region)?;
     util::check_key_in_region(range.get_end(), region)?;
 
+    // `merkle_root` is only populated by uploaders new enough to compute
+    // it, so an empty field skips verification rather than rejecting SSTs
+    // produced before this check existed.
+    let expected_root = sst.get_merkle_root();
+    if !expected_root.is_empty() {
+        let data = importer
+            .read_sst_bytes(sst)
+            .map_err(|e| box_err!("failed to read sst {:?} for merkle check: {:?}", uuid, e))?;
+        let actual_root = sst_merkle::encode_root(sst_merkle::root(&data));
+        if actual_root != expected_root {
+            return Err(box_err!(
+                "sst {:?} merkle root mismatch: expected {:?}, computed {:?}",
+                uuid,
+                expected_root,
+                actual_root
+            ));
+        }
+    }
+
     Ok(())
 }
 
@@ -2273,18 +3659,72 @@ pub struct CatchUpLogs {
     pub target_region_id: u64,
     /// Merge request that contains logs to be applied.
     pub merge: CommitMergeRequest,
-    /// A flag indicate that all source region's logs are applied.
-    ///
-    /// This is still necessary although we have a mailbox field already.
-    /// Mailbox is used to notify target region, and trigger a round of polling.
-    /// But due to the FIFO natural of channel, we need a flag to check if it's
-    /// ready when polling.
-    pub logs_up_to_date: Arc<AtomicU64>,
+}
+
+/// Per-(region, peer) record of whether that specific peer has already been
+/// initialized through some means other than a full RocksDB snapshot (e.g.
+/// `FastAddPeer`, see `ChangePeer::fast_add_peer`), consulted by
+/// `GenSnapTask::generate_and_schedule_snapshot` so a `MsgSnapshot` that
+/// raced ahead of append doesn't trigger a wasted full-region scan.
+///
+/// This is keyed by `(region_id, peer_id)`, not just `region_id`: the flag
+/// only means "this peer doesn't need a snapshot", and a region can have
+/// several peers at once in different states (one fast-bootstrapped, another
+/// genuinely behind and needing a real snapshot). Keying on region id alone
+/// would let one peer's fast-add silently swallow another peer's `MsgSnapshot`.
+#[derive(Default)]
+struct CachedRegionInfo {
+    inited_or_fallback: AtomicBool,
+}
+
+/// Registry of [`CachedRegionInfo`], keyed by `(region_id, peer_id)` and
+/// shared across every apply poller in the store (mirrors
+/// `ApplyRouter::pending_commit_merges`), so a peer fast-bootstrapped on one
+/// poller is also recognized by `GenSnapTask`s generated on another.
+#[derive(Clone, Default)]
+pub struct CachedRegionInfoManager {
+    infos: Arc<Mutex<HashMap<(u64, u64), Arc<CachedRegionInfo>>>>,
+}
+
+impl CachedRegionInfoManager {
+    pub fn new() -> CachedRegionInfoManager {
+        CachedRegionInfoManager::default()
+    }
+
+    fn get_or_insert(&self, region_id: u64, peer_id: u64) -> Arc<CachedRegionInfo> {
+        self.infos
+            .lock()
+            .unwrap()
+            .entry((region_id, peer_id))
+            .or_insert_with(|| Arc::new(CachedRegionInfo::default()))
+            .clone()
+    }
+
+    fn is_inited_or_fallback(&self, region_id: u64, peer_id: u64) -> bool {
+        self.infos
+            .lock()
+            .unwrap()
+            .get(&(region_id, peer_id))
+            .map_or(false, |info| {
+                info.inited_or_fallback.load(Ordering::Acquire)
+            })
+    }
+
+    /// Marks `(region_id, peer_id)` as already initialized (`inited = true`),
+    /// so a subsequent `generate_and_schedule_snapshot` for that peer that
+    /// raced ahead of append is filtered, or clears the flag
+    /// (`inited = false`) so the next request for that peer falls back to a
+    /// real snapshot again.
+    pub fn set_inited_or_fallback(&self, region_id: u64, peer_id: u64, inited: bool) {
+        self.get_or_insert(region_id, peer_id)
+            .inited_or_fallback
+            .store(inited, Ordering::Release);
+    }
 }
 
 pub struct GenSnapTask {
     pub(crate) region_id: u64,
-    //pub(crate) peer_id: u64,
+    pub(crate) to_peer_id: u64,
     commit_index: u64,
     snap_notifier: SyncSender<RaftSnapshot>,
 }
@@ -2292,13 +3732,13 @@ pub struct GenSnapTask {
 impl GenSnapTask {
     pub fn new(
         region_id: u64,
-        //peer_id: u64,
+        to_peer_id: u64,
         commit_index: u64,
         snap_notifier: SyncSender<RaftSnapshot>,
     ) -> GenSnapTask {
         GenSnapTask {
             region_id,
-            //peer_id,
+            to_peer_id,
             commit_index,
             snap_notifier,
         }
@@ -2308,13 +3748,51 @@ impl GenSnapTask {
         self.commit_index
     }
 
+    /// Schedules a `RegionTask::Gen` for this task's region, unless
+    /// `cached_region_info` already has `(region_id, to_peer_id)` marked
+    /// initialized, in which case this is a stale/redundant request for
+    /// `to_peer_id` specifically (e.g. it raced ahead of a `FastAddPeer`
+    /// bootstrap) and is dropped without scheduling a full scan. Scheduling a
+    /// normal snapshot here does not itself set the flag - only a
+    /// `FastAddPeer` bootstrap does that (see `ChangePeer::fast_add_peer`) -
+    /// so a later, genuinely-behind peer of the same region still gets its
+    /// own snapshot.
+    ///
+    /// Before scheduling, each CF is run through `snapshot_chunks` to see
+    /// how much of it `self.to_peer_id` already holds from an earlier
+    /// snapshot - see [`snapshot_chunking`] - so a slow follower or a
+    /// rebalance that re-targets the same store doesn't retransmit bytes
+    /// the receiver already has. This is purely informational accounting
+    /// today (the generated snapshot still carries the full `kv_snap`);
+    /// a read failure while chunking must not block scheduling the real
+    /// snapshot, so it's best-effort.
     pub fn generate_and_schedule_snapshot(
         self,
         kv_snap: RocksSnapshot,
         last_applied_index_term: u64,
         last_applied_state: RaftApplyState,
         region_sched: &Scheduler<RegionTask>,
+        cached_region_info: &CachedRegionInfoManager,
+        snapshot_chunks: &snapshot_chunking::SnapshotChunkRegistry,
     ) -> Result<()> {
+        if cached_region_info.is_inited_or_fallback(self.region_id, self.to_peer_id) {
+            return Ok(());
+        }
+        for &cf in ALL_CFS {
+            if let Ok(data) = kv_snap.cf_bytes_for_chunking(cf) {
+                let (manifest, stats) = snapshot_chunks.diff(self.to_peer_id, &data);
+                debug!(
+                    "chunked snapshot cf for cross-snapshot dedup";
+                    "region_id" => self.region_id,
+                    "to_peer_id" => self.to_peer_id,
+                    "cf" => cf,
+                    "chunks" => manifest.len(),
+                    "missing_chunks" => stats.missing_chunks,
+                    "transferred_bytes" => stats.transferred_bytes,
+                    "total_bytes" => stats.total_bytes,
+                );
+            }
+        }
         let snapshot = RegionTask::Gen {
             region_id: self.region_id,
             notifier: self.snap_notifier,
@@ -2333,6 +3811,7 @@ impl Debug for GenSnapTask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("GenSnapTask")
             .field("region_id", &self.region_id)
+            .field("to_peer_id", &self.to_peer_id)
             .field("commit_index", &self.commit_index)
             .finish()
     }
@@ -2368,6 +3847,10 @@ pub enum ChangeCmd {
         region_id: u64,
         enabled: Arc<AtomicBool>,
     },
+    DeregisterObserver {
+        observe_id: ObserveID,
+        region_id: u64,
+    },
     Snapshot {
         observe_id: ObserveID,
         region_id: u64,
@@ -2382,6 +3865,19 @@ pub enum Msg {
     Registration(Registration),
     Proposal(RegionProposal),
     LogsUpToDate(CatchUpLogs),
+    /// Sent by a source peer's apply fsm, once it has caught up to the
+    /// required index, to resume the target peer's `CommitMerge`. Carries
+    /// the source region id so the target doesn't need to read it back out
+    /// of any shared state.
+    MergeSourceReady(u64),
+    /// Gracefully advances a delegate's [`RegionRoleState`], e.g. as a peer
+    /// starts transferring leadership away or flushes before a snapshot.
+    SetRoleState(u64, RegionRoleState),
+    /// Sets (`true`) or clears (`false`) the cached "already initialized"
+    /// flag for (region id, peer id), consulted by
+    /// `GenSnapTask::generate_and_schedule_snapshot` to skip a redundant
+    /// snapshot for a peer bootstrapped some other way (e.g. `FastAddPeer`).
+    SetRegionCacheInit(u64, u64, bool),
     Noop,
     Destroy(Destroy),
     Change {
@@ -2394,6 +3890,15 @@ pub enum Msg {
         region_id: u64,
         sync: bool,
     },
+    /// A cheap, non-blocking probe for a delegate's current apply progress,
+    /// so operators and the store fsm can poll per-region apply lag
+    /// (committed-vs-applied gap) on a timer and feed it into metrics,
+    /// without draining the delegate's queue or paying for the heavier,
+    /// test-only `Validate` closure machinery below.
+    HealthCheck {
+        region_id: u64,
+        cb: Box<dyn FnOnce(Result<ApplyHealthStatus, Error>) + Send>,
+    },
     #[cfg(any(test, feature = "testexport"))]
     Validate(u64, Box<dyn FnOnce((&ApplyDelegate, bool)) + Send>),
 }
@@ -2416,6 +3921,14 @@ impl Msg {
             async_remove,
         })
     }
+
+    pub fn merge_source_ready(source_region_id: u64) -> Msg {
+        Msg::MergeSourceReady(source_region_id)
+    }
+
+    pub fn set_role_state(region_id: u64, state: RegionRoleState) -> Msg {
+        Msg::SetRoleState(region_id, state)
+    }
 }
 
 impl Debug for Msg {
@@ -2427,6 +3940,17 @@ impl Debug for Msg {
                 write!(f, "[region {}] Reg {:?}", r.region.get_id(), r.apply_state)
             }
             Msg::LogsUpToDate(_) => write!(f, "logs are updated"),
+            Msg::MergeSourceReady(source_region_id) => {
+                write!(f, "merge source [region {}] ready", source_region_id)
+            }
+            Msg::SetRoleState(region_id, state) => {
+                write!(f, "[region {}] set role state to {:?}", region_id, state)
+            }
+            Msg::SetRegionCacheInit(region_id, peer_id, inited) => write!(
+                f,
+                "[region {}] set cached init flag for peer {} to {}",
+                region_id, peer_id, inited
+            ),
             Msg::Noop => write!(f, "noop"),
             Msg::Destroy(ref d) => write!(f, "[region {}] destroy", d.region_id),
             Msg::Snapshot { region_id, .. } => {
@@ -2436,10 +3960,17 @@ impl Debug for Msg {
                 cmd: ChangeCmd::RegisterObserver { region_id, .. },
                 ..
             } => write!(f, "[region {}] registers cmd observer", region_id),
+            Msg::Change {
+                cmd: ChangeCmd::DeregisterObserver { region_id, .. },
+                ..
+            } => write!(f, "[region {}] deregisters cmd observer", region_id),
             Msg::Change {
                 cmd: ChangeCmd::Snapshot { region_id, .. },
                 ..
             } => write!(f, "[region {}] cmd snapshot", region_id),
+            Msg::HealthCheck { region_id, .. } => {
+                write!(f, "[region {}] health check", region_id)
+            }
             #[cfg(any(test, feature = "testexport"))]
             Msg::Validate(region_id, _) => write!(f, "[region {}] validate", region_id),
         }
@@ -2456,6 +3987,34 @@ pub struct ApplyMetrics {
     pub written_bytes: u64,
     pub written_keys: u64,
     pub lock_cf_written_bytes: u64,
+
+    /// Apply-phase latency breakdown for the entries handled since this
+    /// `ApplyMetrics` was last reset. Carried on `ApplyRes` so the raftstore
+    /// can attach it to the `write_detail` of the command execution details
+    /// returned to clients, mirroring the write-path `WriteDetail`/
+    /// `ScanDetail` tracker pattern.
+    pub timing: ApplyTimingDetail,
+}
+
+/// Snapshot of a delegate's apply progress, answered by `Msg::HealthCheck`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplyHealthStatus {
+    /// The last entry index this delegate has applied.
+    pub applied_index: u64,
+    /// The term of the raft log at `applied_index`.
+    pub applied_index_term: u64,
+    /// The last applied index known to be durable on local storage; see
+    /// `ApplyDelegate::data_is_durable`.
+    pub last_sync_apply_index: u64,
+    /// Whether the delegate is about to be destroyed.
+    pub pending_remove: bool,
+    /// Whether the delegate has stopped polling altogether.
+    pub stopped: bool,
+    /// Whether the delegate has yielded mid-batch, e.g. waiting to be
+    /// resumed after writing too much data in one tick.
+    pub yielded: bool,
+    /// Whether the delegate is blocked on a `CommitMerge` source catching up.
+    pub wait_merge_state: bool,
 }
 
 #[derive(Debug)]
@@ -2558,8 +4117,10 @@ impl ApplyFsm {
         self.delegate.apply_state.set_commit_index(cur_state.1);
         self.delegate.apply_state.set_commit_term(cur_state.2);
 
+        let apply_log_begin = Instant::now_coarse();
         self.delegate
             .handle_raft_committed_entries(apply_ctx, apply.entries);
+        apply_ctx.timing.apply_log_nanos += duration_to_nanos(apply_log_begin.elapsed());
         if self.delegate.yield_state.is_some() {
             return;
         }
@@ -2577,6 +4138,17 @@ impl ApplyFsm {
             }
             return;
         }
+        if self.delegate.should_reject_write() {
+            // This delegate is finalizing a merge or handing off
+            // leadership; reject the whole batch so the client backs off
+            // and retries rather than queuing proposals behind a handoff
+            // that may never resume them.
+            for p in region_proposal.props {
+                let cmd = PendingCmd::new(p.index, p.term, p.cb);
+                notify_stale_command(region_id, peer_id, self.delegate.term, cmd);
+            }
+            return;
+        }
         for p in region_proposal.props {
             let cmd = PendingCmd::new(p.index, p.term, p.cb);
             if p.is_conf_change {
@@ -2641,19 +4213,15 @@ impl ApplyFsm {
         }
     }
 
+    /// Resumes a delegate that yielded for a reason other than waiting on a
+    /// merge source (e.g. it wrote too much data in one go). A merge-source
+    /// wait is resumed directly by `on_merge_source_ready` instead, since
+    /// that event arrives as a message rather than something to poll for
+    /// here.
     fn resume_pending<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
         &mut self,
         ctx: &mut ApplyContext<W>,
     ) -> bool {
-        if let Some(ref state) = self.delegate.wait_merge_state {
-            let source_region_id = state.logs_up_to_date.load(Ordering::SeqCst);
-            if source_region_id == 0 {
-                return false;
-            }
-            self.delegate.ready_source_region_id = source_region_id;
-        }
-        self.delegate.wait_merge_state = None;
-
         let mut state = self.delegate.yield_state.take().unwrap();
 
         if ctx.timer.is_none() {
@@ -2682,6 +4250,26 @@ impl ApplyFsm {
         true
     }
 
+    /// Handles `Msg::MergeSourceReady`, sent by the source peer's apply fsm
+    /// once it has applied to the index our `CommitMerge` is waiting on.
+    /// Resumes the yielded `CommitMerge` immediately rather than waiting for
+    /// the next scheduled poll.
+    fn on_merge_source_ready<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
+        &mut self,
+        ctx: &mut ApplyContext<W>,
+        source_region_id: u64,
+    ) {
+        if !self.delegate.wait_merge_state {
+            // Stale message: we've already moved past this merge (or been
+            // destroyed). Nothing to resume.
+            return;
+        }
+        self.delegate.wait_merge_state = false;
+        self.delegate.ready_source_region_id = source_region_id;
+        ctx.router.clear_pending_commit_merge(source_region_id);
+        self.resume_pending(ctx);
+    }
+
     fn logs_up_to_date_for_merge<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
         &mut self,
         ctx: &mut ApplyContext<W>,
@@ -2703,12 +4291,12 @@ impl ApplyFsm {
         // The source peer fsm will be destroyed when the target peer executes `on_ready_commit_merge`
         // and sends `merge result` to the source peer fsm.
         self.destroy(ctx);
-        catch_up_logs
-            .logs_up_to_date
-            .store(region_id, Ordering::SeqCst);
-        // To trigger the target apply fsm
+        // Wake the target apply fsm directly with the ready region id, instead of
+        // flipping a shared flag and poking it with a generic `Msg::Noop`: the
+        // readiness signal and its payload now arrive together, atomically, so
+        // there's nothing left for the target to poll.
         if let Some(mailbox) = ctx.router.mailbox(catch_up_logs.target_region_id) {
-            let _ = mailbox.force_send(Msg::Noop);
+            let _ = mailbox.force_send(Msg::merge_source_ready(region_id));
         } else {
             error!(
                 "failed to get mailbox, are we shutting down?";
@@ -2782,23 +4370,20 @@ impl ApplyFsm {
         region_epoch: RegionEpoch,
         cb: Callback<RocksEngine>,
     ) {
-        let (observe_id, region_id, enabled) = match cmd {
+        let (observe_id, region_id, enabled, deregister) = match cmd {
             ChangeCmd::RegisterObserver {
                 observe_id,
                 region_id,
                 enabled,
-            } => {
-                assert!(!self
-                    .delegate
-                    .observe_cmd
-                    .as_ref()
-                    .map_or(false, |o| o.enabled.load(Ordering::SeqCst)));
-                (observe_id, region_id, Some(enabled))
-            }
+            } => (observe_id, region_id, Some(enabled), false),
+            ChangeCmd::DeregisterObserver {
+                observe_id,
+                region_id,
+            } => (observe_id, region_id, None, true),
             ChangeCmd::Snapshot {
                 observe_id,
                 region_id,
-            } => (observe_id, region_id, None),
+            } => (observe_id, region_id, None, false),
         };
 
         assert_eq!(self.delegate.region_id(), region_id);
@@ -2827,19 +4412,35 @@ impl ApplyFsm {
                 snapshot: None,
             },
         };
-        if let Some(enabled) = enabled {
-            // TODO(cdc): take observe_cmd when enabled is false.
-            self.delegate.observe_cmd = Some(ObserveCmd {
-                id: observe_id,
-                enabled,
-            });
-        } else if let Some(observe_cmd) = self.delegate.observe_cmd.as_mut() {
-            observe_cmd.id = observe_id;
+        if deregister {
+            self.delegate.observe_cmds.remove(&observe_id);
+        } else if let Some(enabled) = enabled {
+            self.delegate.observe_cmds.insert(
+                observe_id,
+                ObserveCmd {
+                    id: observe_id,
+                    enabled,
+                },
+            );
         }
 
         cb.invoke_read(resp);
     }
 
+    /// Answers `Msg::HealthCheck` with a snapshot of this delegate's apply
+    /// progress, without touching the write path or blocking on anything.
+    fn handle_health_check(&self, cb: Box<dyn FnOnce(Result<ApplyHealthStatus, Error>) + Send>) {
+        cb(Ok(ApplyHealthStatus {
+            applied_index: self.delegate.apply_state.get_applied_index(),
+            applied_index_term: self.delegate.applied_index_term,
+            last_sync_apply_index: self.delegate.last_sync_apply_index,
+            pending_remove: self.delegate.pending_remove,
+            stopped: self.delegate.stopped,
+            yielded: self.delegate.yield_state.is_some(),
+            wait_merge_state: self.delegate.wait_merge_state,
+        }));
+    }
+
     fn handle_tasks<W: WriteBatch + WriteBatchVecExt<RocksEngine>>(
         &mut self,
         apply_ctx: &mut ApplyContext<W>,
@@ -2853,6 +4454,7 @@ impl ApplyFsm {
                     if channel_timer.is_none() {
                         channel_timer = Some(start);
                     }
+                    apply_ctx.timing.apply_batch_wait_nanos += duration_to_nanos(start.elapsed());
                     self.handle_apply(apply_ctx, apply);
                     if let Some(ref mut state) = self.delegate.yield_state {
                         state.pending_msgs = drainer.collect();
@@ -2863,6 +4465,15 @@ impl ApplyFsm {
                 Some(Msg::Registration(reg)) => self.handle_registration(reg),
                 Some(Msg::Destroy(d)) => self.handle_destroy(apply_ctx, d),
                 Some(Msg::LogsUpToDate(cul)) => self.logs_up_to_date_for_merge(apply_ctx, cul),
+                Some(Msg::MergeSourceReady(source_region_id)) => {
+                    self.on_merge_source_ready(apply_ctx, source_region_id)
+                }
+                Some(Msg::SetRoleState(_, state)) => {
+                    self.delegate.set_leader_role_state_gracefully(state)
+                }
+                Some(Msg::SetRegionCacheInit(region_id, peer_id, inited)) => apply_ctx
+                    .cached_region_info
+                    .set_inited_or_fallback(region_id, peer_id, inited),
                 Some(Msg::Noop) => {}
                 Some(Msg::Snapshot { cb, sync, .. }) => self.handle_snapshot(apply_ctx, cb, sync),
                 Some(Msg::Change {
@@ -2870,6 +4481,7 @@ impl ApplyFsm {
                     region_epoch,
                     cb,
                 }) => self.handle_change(apply_ctx, cmd, region_epoch, cb),
+                Some(Msg::HealthCheck { cb, .. }) => self.handle_health_check(cb),
                 #[cfg(any(test, feature = "testexport"))]
                 Some(Msg::Validate(_, f)) => f((&self.delegate, apply_ctx.enable_sync_log)),
                 None => break,
@@ -2926,15 +4538,18 @@ impl Fsm for ControlFsm {
     }
 }
 
-pub struct ApplyPoller<W: WriteBatch + WriteBatchVecExt<RocksEngine>> {
+pub struct ApplyPoller<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static> {
     msg_buf: Vec<Msg>,
     apply_ctx: ApplyContext<W>,
     messages_per_tick: usize,
     cfg_tracker: Tracker<Config>,
+    /// Last time `retry_pending_commit_merges` ran; see
+    /// `PENDING_COMMIT_MERGE_RETRY_INTERVAL`.
+    last_commit_merge_retry: Instant,
 }
 
-impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> PollHandler<ApplyFsm, ControlFsm>
-    for ApplyPoller<W>
+impl<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static>
+    PollHandler<ApplyFsm, ControlFsm> for ApplyPoller<W>
 {
     fn begin(&mut self, _batch_size: usize) {
         if let Some(incoming) = self.cfg_tracker.any_new() {
@@ -2950,6 +4565,18 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> PollHandler<ApplyFsm, Contro
                 _ => {}
             }
             self.apply_ctx.enable_sync_log = incoming.sync_log;
+            self.apply_ctx.apply_bytes_per_tick = incoming.apply_bytes_per_tick;
+            self.apply_ctx.max_ingest_attempts = if incoming.max_ingest_attempts > 0 {
+                incoming.max_ingest_attempts
+            } else {
+                DEFAULT_MAX_INGEST_ATTEMPTS
+            };
+        }
+        if self.last_commit_merge_retry.elapsed() >= PENDING_COMMIT_MERGE_RETRY_INTERVAL {
+            self.apply_ctx
+                .router
+                .retry_pending_commit_merges(&self.apply_ctx.notifier);
+            self.last_commit_merge_retry = Instant::now_coarse();
         }
     }
 
@@ -2961,13 +4588,11 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> PollHandler<ApplyFsm, Contro
     fn handle_normal(&mut self, normal: &mut ApplyFsm) -> Option<usize> {
         let mut expected_msg_count = None;
         normal.delegate.written = false;
-        if normal.delegate.yield_state.is_some() {
-            if normal.delegate.wait_merge_state.is_some() {
-                // We need to query the length first, otherwise there is a race
-                // condition that new messages are queued after resuming and before
-                // query the length.
-                expected_msg_count = Some(normal.receiver.len());
-            }
+        normal.delegate.tick_written_bytes = 0;
+        // A merge-source wait is resumed by `Msg::MergeSourceReady` as it's
+        // handled below, not by polling here, so only non-merge yields (e.g.
+        // a batch that wrote too much data) are resumed eagerly.
+        if normal.delegate.yield_state.is_some() && !normal.delegate.wait_merge_state {
             if !normal.resume_pending(&mut self.apply_ctx) {
                 return expected_msg_count;
             }
@@ -2988,11 +4613,9 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> PollHandler<ApplyFsm, Contro
             }
         }
         normal.handle_tasks(&mut self.apply_ctx, &mut self.msg_buf);
-        if normal.delegate.wait_merge_state.is_some() {
-            // Check it again immediately as catching up logs can be very fast.
-            expected_msg_count = Some(0);
-        } else if normal.delegate.yield_state.is_some() {
-            // Let it continue to run next time.
+        if normal.delegate.yield_state.is_some() {
+            // Let it continue to run next time. If still merge-waiting,
+            // `Msg::MergeSourceReady` will wake this mailbox directly.
             expected_msg_count = None;
         }
         expected_msg_count
@@ -3008,7 +4631,7 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> PollHandler<ApplyFsm, Contro
     }
 }
 
-pub struct Builder<W: WriteBatch + WriteBatchVecExt<RocksEngine>> {
+pub struct Builder<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static> {
     tag: String,
     cfg: Arc<VersionTrack<Config>>,
     coprocessor_host: CoprocessorHost,
@@ -3016,10 +4639,34 @@ pub struct Builder<W: WriteBatch + WriteBatchVecExt<RocksEngine>> {
     engine: RocksEngine,
     sender: Notifier,
     router: ApplyRouter,
+    // Shared across every `ApplyContext` this builder creates, so SST dedup
+    // applies store-wide rather than per apply poller.
+    chunk_store: Arc<content_chunking::ChunkStore>,
+    // Shared across every `ApplyContext` this builder creates, so a peer
+    // fast-bootstrapped on one apply poller is also recognized by
+    // `GenSnapTask`s generated on another.
+    cached_region_info: CachedRegionInfoManager,
+    // Dead-letter queue for ingests that fail at the `importer` boundary,
+    // shared store-wide alongside `importer` itself so an operator has one
+    // place to enumerate quarantined SSTs regardless of which apply poller
+    // hit the failure.
+    ingest_quarantine: Arc<ingest_quarantine::IngestQuarantine>,
+    // Shared across every `ApplyContext` this builder creates, so
+    // `merkle_root`/`merkle_children` answer for a region regardless of
+    // which apply poller is applying it.
+    region_merkle: RegionMerkleManager,
+    // Shared across every `ApplyContext` this builder creates, so a peer's
+    // known chunk set from a snapshot generated on one apply poller is
+    // still recognized by a `GenSnapTask` generated on another.
+    snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry,
+    // Shared across every `ApplyContext` this builder creates, so a CDC or
+    // replication consumer gets the same accumulator root/proofs for a
+    // region regardless of which apply poller applied a given entry.
+    entry_log: EntryAccumulatorManager,
     _phantom: PhantomData<W>,
 }
 
-impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> Builder<W> {
+impl<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static> Builder<W> {
     pub fn new<T, C>(
         builder: &RaftPollerBuilder<T, C>,
         sender: Notifier,
@@ -3031,15 +4678,69 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> Builder<W> {
             coprocessor_host: builder.coprocessor_host.clone(),
             importer: builder.importer.clone(),
             engine: builder.engines.kv.clone(),
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
             _phantom: PhantomData,
             sender,
             router,
         }
     }
+
+    /// Lists every `IngestSst` currently quarantined after repeatedly
+    /// failing at the importer boundary, so an operator can decide whether
+    /// to re-stage the source file or drop it for good.
+    pub fn quarantined_ingests(&self) -> Vec<ingest_quarantine::QuarantinedIngest> {
+        self.ingest_quarantine.list()
+    }
+
+    /// The shared chunk-dedup registry consulted by
+    /// `GenSnapTask::generate_and_schedule_snapshot`, so the caller that
+    /// drives that method (outside the apply poller) uses the same
+    /// per-peer chunk state this builder's apply contexts do.
+    pub fn snapshot_chunks(&self) -> snapshot_chunking::SnapshotChunkRegistry {
+        self.snapshot_chunks.clone()
+    }
+
+    /// This region's current Merkle root, for an operator or peer to
+    /// compare against another replica's - see [`RegionMerkleManager`].
+    pub fn merkle_root(&self, region_id: u64) -> Option<u64> {
+        self.region_merkle.merkle_root(region_id)
+    }
+
+    /// Hashes of the children at `node_path` within `region_id`'s Merkle
+    /// tree, for descending to the leaf range a root mismatch traces back
+    /// to - see [`region_merkle::RegionMerkle::children`].
+    pub fn merkle_children(&self, region_id: u64, node_path: &[u8]) -> Vec<(Vec<u8>, u64)> {
+        self.region_merkle.merkle_children(region_id, node_path)
+    }
+
+    /// `region_id`'s current applied-entry accumulator root, for a CDC or
+    /// replication consumer to publish alongside the last batch it
+    /// delivered - see [`EntryAccumulatorManager`].
+    pub fn entry_log_root(&self, region_id: u64) -> Option<[u8; 32]> {
+        self.entry_log.root(region_id)
+    }
+
+    /// An inclusion proof for `leaf_index` within `region_id`'s accumulator,
+    /// letting a consumer that fell behind confirm the batches it's about
+    /// to catch up on extend the contiguous log it already verified,
+    /// rather than a reordered or gapped one - see
+    /// [`EntryAccumulatorManager::prove`].
+    pub fn entry_log_prove(
+        &self,
+        region_id: u64,
+        leaf_index: u64,
+    ) -> Option<entry_accumulator::InclusionProof> {
+        self.entry_log.prove(region_id, leaf_index)
+    }
 }
 
-impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> HandlerBuilder<ApplyFsm, ControlFsm>
-    for Builder<W>
+impl<W: WriteBatch + WriteBatchVecExt<RocksEngine> + Send + 'static>
+    HandlerBuilder<ApplyFsm, ControlFsm> for Builder<W>
 {
     type Handler = ApplyPoller<W>;
 
@@ -3055,16 +4756,40 @@ impl<W: WriteBatch + WriteBatchVecExt<RocksEngine>> HandlerBuilder<ApplyFsm, Con
                 self.router.clone(),
                 self.sender.clone(),
                 &cfg,
+                self.chunk_store.clone(),
+                self.cached_region_info.clone(),
+                self.ingest_quarantine.clone(),
+                self.region_merkle.clone(),
+                self.snapshot_chunks.clone(),
+                self.entry_log.clone(),
             ),
             messages_per_tick: cfg.messages_per_tick,
             cfg_tracker: self.cfg.clone().tracker(self.tag.clone()),
+            last_commit_merge_retry: Instant::now_coarse(),
         }
     }
 }
 
+/// A `CommitMerge` the target is waiting on the source to catch up for,
+/// recorded so the wait can be retried instead of relying solely on the
+/// one-shot `Msg::MergeSourceReady` notification: that notification is a
+/// best-effort `force_send` which is dropped if the source's mailbox isn't
+/// registered yet (e.g. the source peer hasn't been created on this store)
+/// or the process restarts before it's delivered.
+#[derive(Debug, Clone)]
+struct PendingCommitMerge {
+    target_region_id: u64,
+    merge: CommitMergeRequest,
+    registered_at: Instant,
+}
+
 #[derive(Clone)]
 pub struct ApplyRouter {
     pub router: BatchRouter<ApplyFsm, ControlFsm>,
+    // Keyed by source_region_id. Shared across every clone of this router,
+    // since the target and source regions of a merge can live on different
+    // apply pollers.
+    pending_commit_merges: Arc<Mutex<HashMap<u64, PendingCommitMerge>>>,
 }
 
 impl Deref for ApplyRouter {
@@ -3125,6 +4850,11 @@ impl ApplyRouter {
                     cb,
                     ..
                 }
+                | Msg::Change {
+                    cmd: ChangeCmd::DeregisterObserver { region_id, .. },
+                    cb,
+                    ..
+                }
                 | Msg::Change {
                     cmd: ChangeCmd::Snapshot { region_id, .. },
                     cb,
@@ -3139,6 +4869,12 @@ impl ApplyRouter {
                     cb.invoke_read(resp);
                     return;
                 }
+                Msg::HealthCheck { region_id, cb } => {
+                    warn!("target region is not found";
+                            "region_id" => region_id);
+                    cb(Err(Error::RegionNotFound(region_id)));
+                    return;
+                }
                 #[cfg(any(test, feature = "testexport"))]
                 Msg::Validate(_, _) => return,
             },
@@ -3153,6 +4889,66 @@ impl ApplyRouter {
         let mailbox = BasicMailbox::new(sender, apply_fsm);
         self.register(region_id, mailbox);
     }
+
+    /// Records that `target_region_id` is waiting on `source_region_id` to
+    /// catch up to `merge`'s required index. This registry itself is plain
+    /// in-memory state and is lost on restart like everything else on
+    /// `ApplyRouter` - what actually survives a restart of the target is the
+    /// `CommitMerge` entry itself, since `WaitMergeSource` yields before
+    /// `apply_state` advances past it, so it's replayed (and this re-called)
+    /// once the target's apply fsm comes back up. What this registry buys is
+    /// surviving a *lost* `Msg::MergeSourceReady` while the target stays up:
+    /// `ApplyPoller::begin` periodically calls `retry_pending_commit_merges`
+    /// to re-send `CatchUpLogs` for everything still registered here.
+    /// Re-registering the same source simply refreshes the recorded intent.
+    fn register_pending_commit_merge(
+        &self,
+        source_region_id: u64,
+        target_region_id: u64,
+        merge: CommitMergeRequest,
+    ) {
+        self.pending_commit_merges.lock().unwrap().insert(
+            source_region_id,
+            PendingCommitMerge {
+                target_region_id,
+                merge,
+                registered_at: Instant::now_coarse(),
+            },
+        );
+    }
+
+    /// Forgets a pending `CommitMerge` wait, once the source has reported
+    /// ready or the merge has been abandoned.
+    fn clear_pending_commit_merge(&self, source_region_id: u64) {
+        self.pending_commit_merges
+            .lock()
+            .unwrap()
+            .remove(&source_region_id);
+    }
+
+    /// Re-sends `CatchUpLogs` via `notifier` for every pending `CommitMerge`
+    /// still registered, e.g. on a periodic tick or right after the source
+    /// region's apply fsm has just been created on this store. A source
+    /// that has already caught up will run `logs_up_to_date_for_merge`
+    /// again and resume its target, which is idempotent on the target side
+    /// since `exec_commit_merge` only resumes once `ready_source_region_id`
+    /// matches; a source that's still behind just re-arms the same wait.
+    pub fn retry_pending_commit_merges(&self, notifier: &Notifier) {
+        let pending: Vec<(u64, PendingCommitMerge)> = self
+            .pending_commit_merges
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        for (source_region_id, pending) in pending {
+            let msg = SignificantMsg::CatchUpLogs(CatchUpLogs {
+                target_region_id: pending.target_region_id,
+                merge: pending.merge,
+            });
+            notifier.notify(source_region_id, PeerMsg::SignificantMsg(msg));
+        }
+    }
 }
 
 pub struct ApplyBatchSystem {
@@ -3192,7 +4988,1254 @@ pub fn create_apply_batch_system(cfg: &Config) -> (ApplyRouter, ApplyBatchSystem
         tx,
         Box::new(ControlFsm),
     );
-    (ApplyRouter { router }, ApplyBatchSystem { system })
+    (
+        ApplyRouter {
+            router,
+            pending_commit_merges: Arc::new(Mutex::new(HashMap::default())),
+        },
+        ApplyBatchSystem { system },
+    )
+}
+
+/// Content-defined chunking and content-addressed dedup for SST ingestion.
+///
+/// `handle_ingest_sst` normally hands the whole SST file to `ctx.importer`,
+/// which links or rewrites it as-is even when an overlapping region or a
+/// repeated bulk-load ingests byte-identical data. Running the file through
+/// a [`chunk`] pass first and storing the results in a [`ChunkStore`] lets
+/// identical content across ingests be written once.
+mod content_chunking {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use openssl::sha::sha256;
+
+    /// Chunks never shrink below this, even if a boundary hash match occurs
+    /// sooner - otherwise pathological inputs (e.g. long runs of one byte)
+    /// degenerate into one chunk per byte.
+    const MIN_CHUNK_SIZE: usize = 2 * 1024;
+    /// The size the mask is tuned to produce on average.
+    const AVG_CHUNK_SIZE: usize = 8 * 1024;
+    /// Chunks are always cut here if no hash boundary was found first, so a
+    /// pathological input (e.g. all-zero bytes) can't produce one giant
+    /// chunk.
+    const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+    /// Required below `AVG_CHUNK_SIZE`: more zero bits, harder to satisfy,
+    /// biasing chunks to grow past the small end of the distribution.
+    const MASK_SMALL: u64 = (1 << 15) - 1;
+    /// Required at or above `AVG_CHUNK_SIZE`: fewer zero bits, easier to
+    /// satisfy, pulling the chunk size back down before it reaches
+    /// `MAX_CHUNK_SIZE`. Together these two masks are FastCDC's "normalized
+    /// chunking", which keeps sizes clustered around `AVG_CHUNK_SIZE`
+    /// instead of the long tail a single flat mask produces.
+    const MASK_LARGE: u64 = (1 << 11) - 1;
+
+    /// A reference to a stored chunk: its strong content hash and byte
+    /// length. Two `ChunkRef`s with the same hash are assumed to refer to
+    /// the same bytes.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ChunkRef {
+        pub hash: [u8; 32],
+        pub len: u64,
+    }
+
+    /// Mixes one input byte into the gear-hash rolling checksum. A fixed
+    /// splitmix64-style avalanche, used in place of a precomputed
+    /// random table so the mapping is reproducible without extra state.
+    fn gear(byte: u8) -> u64 {
+        let mut x = (byte as u64)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x ^= x >> 30;
+        x = x.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x ^= x >> 27;
+        x = x.wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+        x
+    }
+
+    /// Splits `data` into content-defined chunks: a gear-hash rolling
+    /// checksum is advanced one byte at a time, and a boundary is cut
+    /// whenever `hash & mask == 0`, subject to the `MIN`/`MAX_CHUNK_SIZE`
+    /// clamps above. Boundaries depend only on the bytes seen since the
+    /// previous cut, so identical byte runs chunk identically no matter
+    /// where they start in the stream - the property that makes dedup
+    /// across independently-generated SSTs possible.
+    pub fn chunk(data: &[u8]) -> Vec<ChunkRef> {
+        let mut refs = Vec::new();
+        let mut start = 0;
+        let mut hash: u64 = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            hash = (hash << 1).wrapping_add(gear(byte));
+            let run = i - start + 1;
+            if run < MIN_CHUNK_SIZE {
+                continue;
+            }
+            let mask = if run < AVG_CHUNK_SIZE {
+                MASK_SMALL
+            } else {
+                MASK_LARGE
+            };
+            if run >= MAX_CHUNK_SIZE || hash & mask == 0 {
+                refs.push(cut(data, start, i + 1));
+                start = i + 1;
+                hash = 0;
+            }
+        }
+        if start < data.len() {
+            refs.push(cut(data, start, data.len()));
+        }
+        refs
+    }
+
+    fn cut(data: &[u8], start: usize, end: usize) -> ChunkRef {
+        ChunkRef {
+            hash: strong_hash(&data[start..end]),
+            len: (end - start) as u64,
+        }
+    }
+
+    /// SHA-256. Two SSTs ingested from different sources can land on the
+    /// same chunk boundaries (the chunking itself only depends on content),
+    /// so the hash that dedups them needs to be collision-resistant against
+    /// more than just accidental overlap - a weak hash would let a crafted
+    /// chunk silently alias over another tenant's data in `chunks`.
+    fn strong_hash(data: &[u8]) -> [u8; 32] {
+        sha256(data)
+    }
+
+    struct ChunkEntry {
+        data: Vec<u8>,
+        ref_count: u64,
+    }
+
+    /// A reference-counted, content-addressed store of chunk bytes, indexed
+    /// by the SST that contributed each set of chunks. Several ingested
+    /// SSTs - overlapping regions, repeated bulk-loads - can share the same
+    /// chunk; deleting the SST that first wrote a chunk (e.g. via
+    /// `DeleteRange` or compaction cleanup) must not drop it while another
+    /// ingested SST still references it.
+    #[derive(Default)]
+    pub struct ChunkStore {
+        chunks: Mutex<HashMap<[u8; 32], ChunkEntry>>,
+        // Remembers which chunk hashes a given SST (by uuid) resolved to, so
+        // `release_sst` knows what to release without re-chunking the file.
+        sst_chunks: Mutex<HashMap<Vec<u8>, Vec<ChunkRef>>>,
+    }
+
+    impl ChunkStore {
+        /// Chunks `data` (the contents of the SST identified by `sst_uuid`),
+        /// storing any chunk not already present and bumping the ref count
+        /// of every chunk - new or pre-existing - this ingest touches.
+        /// Returns the number of chunks newly written, for callers tracking
+        /// write-amplification savings.
+        pub fn put_sst(&self, sst_uuid: Vec<u8>, data: &[u8]) -> usize {
+            let refs = chunk(data);
+            let mut chunks = self.chunks.lock().unwrap();
+            let mut written = 0;
+            let mut offset = 0usize;
+            for r in &refs {
+                let end = offset + r.len as usize;
+                match chunks.get_mut(&r.hash) {
+                    Some(entry) => entry.ref_count += 1,
+                    None => {
+                        chunks.insert(
+                            r.hash,
+                            ChunkEntry {
+                                data: data[offset..end].to_vec(),
+                                ref_count: 1,
+                            },
+                        );
+                        written += 1;
+                    }
+                }
+                offset = end;
+            }
+            drop(chunks);
+            self.sst_chunks.lock().unwrap().insert(sst_uuid, refs);
+            written
+        }
+
+        /// Releases one reference to every chunk the SST identified by
+        /// `sst_uuid` contributed, dropping a chunk's bytes once no
+        /// ingested SST references it anymore. A no-op if that SST was
+        /// never chunked here (e.g. dedup was skipped on ingest).
+        pub fn release_sst(&self, sst_uuid: &[u8]) {
+            let refs = self.sst_chunks.lock().unwrap().remove(sst_uuid);
+            let refs = match refs {
+                Some(refs) => refs,
+                None => return,
+            };
+            let mut chunks = self.chunks.lock().unwrap();
+            for r in &refs {
+                if let Some(entry) = chunks.get_mut(&r.hash) {
+                    entry.ref_count -= 1;
+                    if entry.ref_count == 0 {
+                        chunks.remove(&r.hash);
+                    }
+                }
+            }
+        }
+
+        /// The number of distinct chunks currently stored, for metrics/tests.
+        pub fn len(&self) -> usize {
+            self.chunks.lock().unwrap().len()
+        }
+
+        /// The ordered chunk hashes `put_sst` recorded for `sst_uuid`, or
+        /// `None` if that SST was never chunked here. This is the compact
+        /// record an ingest actually needs to keep - the chunk bytes
+        /// themselves live once in `chunks`, shared across every SST that
+        /// references them.
+        pub fn sst_chunk_hashes(&self, sst_uuid: &[u8]) -> Option<Vec<[u8; 32]>> {
+            self.sst_chunks
+                .lock()
+                .unwrap()
+                .get(sst_uuid)
+                .map(|refs| refs.iter().map(|r| r.hash).collect())
+        }
+
+        /// Re-chunks `data` and compares the result against the hashes
+        /// recorded for `sst_uuid`, so a caller that still has the original
+        /// bytes around (e.g. a retry that re-reads the source file) can
+        /// confirm this store's copy wasn't corrupted by something other
+        /// than the ingest path - a lost write, a bad disk sector - without
+        /// re-downloading from `chunks` chunk by chunk. Returns `false` if
+        /// `sst_uuid` was never chunked here.
+        pub fn verify_sst(&self, sst_uuid: &[u8], data: &[u8]) -> bool {
+            match self.sst_chunk_hashes(sst_uuid) {
+                Some(recorded) => chunk(data).iter().map(|r| r.hash).eq(recorded),
+                None => false,
+            }
+        }
+    }
+}
+
+/// Dead-letter queue for `IngestSst` commands that fail at the engine/
+/// importer boundary (corrupt file, missing SST, checksum mismatch) rather
+/// than on raft-deterministic grounds like a stale epoch - those are already
+/// handled by `ApplyDelegate::pending_ingest_ssts`. A failure here gets
+/// retried up to a bounded number of attempts on later poll cycles instead
+/// of panicking the apply thread or failing the whole command batch, and
+/// stays enumerable afterwards so an operator can re-download or drop it.
+pub(crate) mod ingest_quarantine {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use kvproto::import_sstpb::SstMeta;
+    use tikv_util::time::Instant;
+
+    /// Caps the quarantine list so a store that's continuously failing to
+    /// ingest (e.g. a broken shared filesystem) can't grow it without bound.
+    /// The oldest entry is dropped to make room, same trade-off a bounded
+    /// dead-letter queue makes: keep the most recent failures, the ones an
+    /// operator is most likely to still be able to act on.
+    const MAX_QUARANTINE_LEN: usize = 1024;
+
+    /// One `IngestSst` that has failed at least once, with enough context
+    /// for an operator to find and either re-stage or discard the source
+    /// file.
+    #[derive(Debug, Clone)]
+    pub struct QuarantinedIngest {
+        pub region_id: u64,
+        pub index: u64,
+        pub term: u64,
+        pub sst: SstMeta,
+        pub attempts: u32,
+        pub last_error: String,
+        pub last_attempt: Instant,
+    }
+
+    #[derive(Default)]
+    pub struct IngestQuarantine {
+        entries: Mutex<VecDeque<QuarantinedIngest>>,
+    }
+
+    impl IngestQuarantine {
+        pub fn new() -> IngestQuarantine {
+            IngestQuarantine::default()
+        }
+
+        /// Records one more failed attempt at ingesting `sst`, returning the
+        /// attempt count so far. An existing entry for the same SST (by
+        /// uuid) is updated in place rather than duplicated.
+        pub fn record_failure(
+            &self,
+            region_id: u64,
+            index: u64,
+            term: u64,
+            sst: &SstMeta,
+            err: String,
+        ) -> u32 {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(existing) = entries.iter_mut().find(|e| e.sst.get_uuid() == sst.get_uuid())
+            {
+                existing.attempts += 1;
+                existing.last_error = err;
+                existing.last_attempt = Instant::now_coarse();
+                return existing.attempts;
+            }
+            if entries.len() >= MAX_QUARANTINE_LEN {
+                entries.pop_front();
+            }
+            entries.push_back(QuarantinedIngest {
+                region_id,
+                index,
+                term,
+                sst: sst.clone(),
+                attempts: 1,
+                last_error: err,
+                last_attempt: Instant::now_coarse(),
+            });
+            1
+        }
+
+        /// Clears a quarantined entry, once it either succeeds on retry or
+        /// is given up on after exhausting its attempts.
+        pub fn remove(&self, sst_uuid: &[u8]) {
+            self.entries
+                .lock()
+                .unwrap()
+                .retain(|e| e.sst.get_uuid() != sst_uuid);
+        }
+
+        /// Lists every currently quarantined ingest, for an operator-facing
+        /// status endpoint or `tikv-ctl` command to enumerate.
+        pub fn list(&self) -> Vec<QuarantinedIngest> {
+            self.entries.lock().unwrap().iter().cloned().collect()
+        }
+
+        /// The number of ingests currently quarantined, for metrics/tests.
+        pub fn len(&self) -> usize {
+            self.entries.lock().unwrap().len()
+        }
+    }
+}
+
+/// A deterministic, incrementally-updated Merkle tree over one region's
+/// committed key/value state, so two replicas can compare roots and, on a
+/// mismatch, descend through [`RegionMerkle::children`] to isolate the
+/// divergent leaf range instead of re-scanning the whole region. Shares the
+/// same anti-entropy goal as [`ConsistencyDigest`] but, unlike the digest's
+/// single XOR-folded word, keeps enough structure to localize a mismatch
+/// rather than just detect one.
+pub(crate) mod region_merkle {
+    use std::collections::BTreeMap;
+
+    use super::fnv64;
+
+    /// Children per level, at both the root and the level-1 nodes below it
+    /// (so the tree has `MERKLE_FANOUT * MERKLE_FANOUT` leaves). Wide enough
+    /// that two replicas typically isolate a mismatch within two calls to
+    /// `children` (root, then the one divergent level-1 node), narrow
+    /// enough that recomputing a dirty branch stays cheap.
+    const MERKLE_FANOUT: usize = 16;
+    const LEAF_COUNT: usize = MERKLE_FANOUT * MERKLE_FANOUT;
+
+    /// One leaf's `(key, hash(value))` entries, where `key` is `cf` and the
+    /// data key concatenated so the same origin key in different cfs is a
+    /// separate entry, hashed to its own leaf, instead of colliding.
+    #[derive(Default)]
+    struct Leaf {
+        entries: BTreeMap<Vec<u8>, u64>,
+        hash: Option<u64>,
+    }
+
+    impl Leaf {
+        fn hash(&mut self) -> u64 {
+            if let Some(h) = self.hash {
+                return h;
+            }
+            // BTreeMap iterates in key order, so this fold - and therefore
+            // the leaf hash - doesn't depend on the order entries were
+            // written in, only on the resulting set of entries.
+            let mut buf = Vec::with_capacity(self.entries.len() * 16);
+            for (key, value_hash) in &self.entries {
+                buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                buf.extend_from_slice(key);
+                buf.extend_from_slice(&value_hash.to_le_bytes());
+            }
+            let h = fnv64(&[&buf]);
+            self.hash = Some(h);
+            h
+        }
+    }
+
+    fn leaf_key(cf: &str, key: &[u8]) -> Vec<u8> {
+        let mut merged = Vec::with_capacity(cf.len() + 1 + key.len());
+        merged.extend_from_slice(cf.as_bytes());
+        merged.push(b':');
+        merged.extend_from_slice(key);
+        merged
+    }
+
+    fn leaf_index(merkle_key: &[u8]) -> usize {
+        (fnv64(&[merkle_key]) % LEAF_COUNT as u64) as usize
+    }
+
+    pub(crate) struct RegionMerkle {
+        leaves: Vec<Leaf>,
+        level1_hash: Vec<Option<u64>>,
+        root_hash: Option<u64>,
+    }
+
+    impl Default for RegionMerkle {
+        fn default() -> RegionMerkle {
+            let mut leaves = Vec::with_capacity(LEAF_COUNT);
+            leaves.resize_with(LEAF_COUNT, Leaf::default);
+            RegionMerkle {
+                leaves,
+                level1_hash: vec![None; MERKLE_FANOUT],
+                root_hash: None,
+            }
+        }
+    }
+
+    impl RegionMerkle {
+        pub(crate) fn new() -> RegionMerkle {
+            RegionMerkle::default()
+        }
+
+        /// Folds `(cf, key, hash(value))` into the leaf `key` routes to by a
+        /// hash of `cf` and `key` (not the key's position in the region's
+        /// keyspace), so a leaf's membership doesn't shift as the region's
+        /// actual key range changes across splits/merges - only `reset`
+        /// does that, same as [`ConsistencyDigest`].
+        pub(crate) fn upsert(&mut self, cf: &str, key: &[u8], value: &[u8]) {
+            let merkle_key = leaf_key(cf, key);
+            let idx = leaf_index(&merkle_key);
+            let value_hash = fnv64(&[value]);
+            self.leaves[idx].entries.insert(merkle_key, value_hash);
+            self.dirty(idx);
+        }
+
+        pub(crate) fn remove(&mut self, cf: &str, key: &[u8]) {
+            let merkle_key = leaf_key(cf, key);
+            let idx = leaf_index(&merkle_key);
+            if self.leaves[idx].entries.remove(&merkle_key).is_some() {
+                self.dirty(idx);
+            }
+        }
+
+        fn dirty(&mut self, leaf_idx: usize) {
+            self.leaves[leaf_idx].hash = None;
+            self.level1_hash[leaf_idx / MERKLE_FANOUT] = None;
+            self.root_hash = None;
+        }
+
+        /// Drops every entry, used whenever this region's key range changes
+        /// (split, merge); the delegate then marks the tree incomplete so
+        /// the next `ComputeHash` rebuilds it from the engine - see
+        /// `ApplyDelegate::rebuild_region_merkle`.
+        pub(crate) fn reset(&mut self) {
+            *self = RegionMerkle::new();
+        }
+
+        fn level1(&mut self, i: usize) -> u64 {
+            if let Some(h) = self.level1_hash[i] {
+                return h;
+            }
+            let mut buf = Vec::with_capacity(MERKLE_FANOUT * 8);
+            for leaf in &mut self.leaves[i * MERKLE_FANOUT..(i + 1) * MERKLE_FANOUT] {
+                buf.extend_from_slice(&leaf.hash().to_le_bytes());
+            }
+            let h = fnv64(&[&buf]);
+            self.level1_hash[i] = Some(h);
+            h
+        }
+
+        /// The tree's root hash, recomputing any dirty branch along the way.
+        pub(crate) fn root(&mut self) -> u64 {
+            if let Some(h) = self.root_hash {
+                return h;
+            }
+            let mut buf = Vec::with_capacity(MERKLE_FANOUT * 8);
+            for i in 0..MERKLE_FANOUT {
+                buf.extend_from_slice(&self.level1(i).to_le_bytes());
+            }
+            let h = fnv64(&[&buf]);
+            self.root_hash = Some(h);
+            h
+        }
+
+        /// Hashes of the children at `node_path`: `&[]` for the root's
+        /// `MERKLE_FANOUT` level-1 children, `&[i]` for level-1 node `i`'s
+        /// leaves. An unrecognized (too-deep) path yields no children.
+        pub(crate) fn children(&mut self, node_path: &[u8]) -> Vec<(Vec<u8>, u64)> {
+            match *node_path {
+                [] => (0..MERKLE_FANOUT)
+                    .map(|i| (vec![i as u8], self.level1(i)))
+                    .collect(),
+                [i] => {
+                    let base = i as usize * MERKLE_FANOUT;
+                    (0..MERKLE_FANOUT)
+                        .map(|j| (vec![i, j as u8], self.leaves[base + j].hash()))
+                        .collect()
+                }
+                _ => Vec::new(),
+            }
+        }
+    }
+}
+
+/// Registry of [`region_merkle::RegionMerkle`], keyed by region id and
+/// shared across every apply poller in the store (mirrors
+/// `CachedRegionInfoManager`), so `merkle_root`/`merkle_children` can answer
+/// queries about any region regardless of which poller is applying it.
+///
+/// The tree itself is in-memory only and starts empty whenever a region's
+/// `ApplyDelegate` is (re)created (restart, snapshot install), same as
+/// [`ConsistencyDigest`]. Like the digest, the tree's leaves hold only the
+/// latest hash per live key, so
+/// `ApplyDelegate::rebuild_region_merkle` can reconstruct it exactly from a
+/// live-key engine scan instead of needing to persist node hashes. That
+/// rebuild runs lazily on this region's first `ComputeHash` after the
+/// (re)create, so a `merkle_root`/`merkle_children` query for a region that
+/// hasn't hit `ComputeHash` yet since its last restart may still reflect
+/// only what's been applied since then, not the full current state.
+#[derive(Clone, Default)]
+pub struct RegionMerkleManager {
+    trees: Arc<Mutex<HashMap<u64, Arc<Mutex<region_merkle::RegionMerkle>>>>>,
+}
+
+impl RegionMerkleManager {
+    pub fn new() -> RegionMerkleManager {
+        RegionMerkleManager::default()
+    }
+
+    fn get_or_insert(&self, region_id: u64) -> Arc<Mutex<region_merkle::RegionMerkle>> {
+        self.trees
+            .lock()
+            .unwrap()
+            .entry(region_id)
+            .or_insert_with(|| Arc::new(Mutex::new(region_merkle::RegionMerkle::new())))
+            .clone()
+    }
+
+    /// This region's current Merkle root, for comparison against another
+    /// replica's - `None` if this store has never applied anything for
+    /// `region_id`.
+    pub fn merkle_root(&self, region_id: u64) -> Option<u64> {
+        let tree = self.trees.lock().unwrap().get(&region_id)?.clone();
+        let root = tree.lock().unwrap().root();
+        Some(root)
+    }
+
+    /// Hashes of the children at `node_path` within `region_id`'s tree, so
+    /// replicas that disagree on `merkle_root` can descend to the specific
+    /// leaf range that differs. Empty if `region_id` is unknown to this
+    /// store or `node_path` is deeper than the tree goes.
+    pub fn merkle_children(&self, region_id: u64, node_path: &[u8]) -> Vec<(Vec<u8>, u64)> {
+        let tree = match self.trees.lock().unwrap().get(&region_id) {
+            Some(tree) => tree.clone(),
+            None => return Vec::new(),
+        };
+        tree.lock().unwrap().children(node_path)
+    }
+}
+
+/// Content-defined chunking for cross-snapshot deduplication, used by
+/// `GenSnapTask::generate_and_schedule_snapshot` so a peer that already
+/// holds most of a region's data from an earlier snapshot (a slow follower
+/// catching back up, a rebalance that re-targets the same store) only needs
+/// the chunks it's missing instead of the whole CF again.
+///
+/// Shares [`content_chunking`]'s Gear-hash boundary test - see there for why
+/// boundaries depend only on local content - but cuts on a single
+/// target-size mask rather than that module's normalized small/large pair,
+/// since snapshot payloads don't see the adversarial all-zero-byte runs
+/// bulk-load SSTs can. Dedup state is also kept per-peer here rather than in
+/// one ref-counted pool: what matters for a snapshot transfer is what this
+/// specific receiver already has cached, not what the whole store has ever
+/// chunked.
+mod snapshot_chunking {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Chunks never shrink below this, same rationale as
+    /// `content_chunking::MIN_CHUNK_SIZE`.
+    const MIN_CHUNK_SIZE: usize = 2 * 1024;
+    /// The size the mask below is tuned to produce on average: `2^13`.
+    const AVG_CHUNK_SIZE: usize = 8 * 1024;
+    /// Chunks are always cut here if no hash boundary was found first.
+    const MAX_CHUNK_SIZE: usize = 64 * 1024;
+    /// A boundary is cut whenever the low 13 bits of the rolling hash are
+    /// zero, giving the `AVG_CHUNK_SIZE` above.
+    const BOUNDARY_MASK: u64 = (AVG_CHUNK_SIZE - 1) as u64;
+
+    /// Mixes one input byte into the gear-hash rolling checksum. Same
+    /// splitmix64-style avalanche as `content_chunking::gear`; duplicated
+    /// rather than shared so this module's mask tuning can move
+    /// independently of SST ingest's.
+    fn gear(byte: u8) -> u64 {
+        let mut x = (byte as u64)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x ^= x >> 30;
+        x = x.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x ^= x >> 27;
+        x = x.wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+        x
+    }
+
+    /// One chunk's content id (a strong hash of its bytes) and length. Two
+    /// `ChunkRef`s with the same `content_id` are assumed to be the same
+    /// bytes.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChunkRef {
+        pub content_id: [u8; 32],
+        pub len: u32,
+    }
+
+    /// Splits `data` into content-defined chunks: a gear-hash rolling
+    /// checksum is advanced one byte at a time, and a boundary is cut
+    /// whenever `hash & BOUNDARY_MASK == 0`, subject to the
+    /// `MIN`/`MAX_CHUNK_SIZE` clamps above. Identical byte runs chunk
+    /// identically no matter where they start in the stream, which is what
+    /// lets successive snapshots of the same region share most of their
+    /// chunks even as unrelated parts of the data shift around.
+    pub fn chunk(data: &[u8]) -> Vec<ChunkRef> {
+        let mut refs = Vec::new();
+        let mut start = 0;
+        let mut hash: u64 = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            hash = (hash << 1).wrapping_add(gear(byte));
+            let run = i - start + 1;
+            if run < MIN_CHUNK_SIZE {
+                continue;
+            }
+            if run >= MAX_CHUNK_SIZE || hash & BOUNDARY_MASK == 0 {
+                refs.push(cut(data, start, i + 1));
+                start = i + 1;
+                hash = 0;
+            }
+        }
+        if start < data.len() {
+            refs.push(cut(data, start, data.len()));
+        }
+        refs
+    }
+
+    fn cut(data: &[u8], start: usize, end: usize) -> ChunkRef {
+        ChunkRef {
+            // blake3 rather than a weak hash like the `fnv64` `region_merkle`
+            // uses: a receiver trusts "missing" vs. "already have"
+            // purely off this id, so an accidental collision here would
+            // silently skip transferring bytes the receiver doesn't actually
+            // have.
+            content_id: *blake3::hash(&data[start..end]).as_bytes(),
+            len: (end - start) as u32,
+        }
+    }
+
+    /// Stats for one CF's chunked diff against a peer, logged by
+    /// `generate_and_schedule_snapshot` and useful to an operator comparing
+    /// dedup effectiveness across peers.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct TransferStats {
+        pub missing_chunks: usize,
+        pub transferred_bytes: u64,
+        pub total_bytes: u64,
+    }
+
+    /// One peer's view of the chunk content ids it's already known to hold,
+    /// built up from chunks this store has diffed against it in prior
+    /// snapshots. An empty store - nothing ever sent to this peer before -
+    /// is the whole-file-transfer fallback: every chunk of the first diff
+    /// comes back missing, same as if dedup weren't in play at all.
+    #[derive(Default)]
+    struct PeerChunkStore {
+        known: HashMap<[u8; 32], u32>,
+    }
+
+    impl PeerChunkStore {
+        /// Chunks `data` and diffs it against the chunks already known,
+        /// returning the manifest - the ordered content ids a receiver
+        /// would need to reconstruct `data` - and stats for the bytes that
+        /// actually have to cross the wire. Every manifest chunk, new or
+        /// already known, is (re-)recorded as known, since after this diff
+        /// is acted on the peer holds it either way.
+        fn diff(&mut self, data: &[u8]) -> (Vec<[u8; 32]>, TransferStats) {
+            let refs = chunk(data);
+            let mut stats = TransferStats {
+                total_bytes: data.len() as u64,
+                ..Default::default()
+            };
+            let manifest = refs.iter().map(|r| r.content_id).collect();
+            for r in &refs {
+                if self.known.insert(r.content_id, r.len).is_none() {
+                    stats.missing_chunks += 1;
+                    stats.transferred_bytes += u64::from(r.len);
+                }
+            }
+            (manifest, stats)
+        }
+    }
+
+    /// Registry of [`PeerChunkStore`], keyed by the receiving peer id and
+    /// shared across every apply poller in the store (mirrors
+    /// `RegionMerkleManager`), so a peer's known chunk set survives
+    /// regardless of which poller generated its last snapshot.
+    #[derive(Clone, Default)]
+    pub struct SnapshotChunkRegistry {
+        peers: Arc<Mutex<HashMap<u64, PeerChunkStore>>>,
+    }
+
+    impl SnapshotChunkRegistry {
+        pub fn new() -> SnapshotChunkRegistry {
+            SnapshotChunkRegistry::default()
+        }
+
+        /// Diffs `data` against `to_peer_id`'s known chunk set - see
+        /// [`PeerChunkStore::diff`].
+        pub fn diff(&self, to_peer_id: u64, data: &[u8]) -> (Vec<[u8; 32]>, TransferStats) {
+            self.peers
+                .lock()
+                .unwrap()
+                .entry(to_peer_id)
+                .or_default()
+                .diff(data)
+        }
+    }
+}
+
+/// Fan-out registry for `CmdBatch` subscribers, so independent downstream
+/// consumers of a region's applied command stream (an incremental-backup
+/// job, a live change feed) can run concurrently off one `CmdObserver`
+/// instead of contending for a single sink, and a slow consumer applies
+/// back-pressure instead of growing memory without bound.
+///
+/// Each subscriber gets its own bounded channel (the same `channel::bounded`
+/// used for `flush_tx` above); `dispatch` blocks on a full channel, so a
+/// stalled subscriber throttles whatever's calling `dispatch` for that
+/// region rather than letting batches pile up, and a subscriber whose
+/// receiver has disconnected is dropped rather than panicking the caller.
+pub(crate) mod cmd_sink {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use crossbeam::channel::{self, Receiver, Sender};
+
+    use crate::coprocessor::CmdBatch;
+
+    static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(0);
+
+    /// Identifies one registered subscriber, so it can be deregistered or
+    /// have its lag queried independently of the others.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct SubscriberId(u64);
+
+    impl SubscriberId {
+        fn new() -> SubscriberId {
+            SubscriberId(NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed))
+        }
+    }
+
+    /// The applied-index range one dispatched `CmdBatch` covers - computed
+    /// by the caller of `dispatch` (typically `CmdBatch::applied_index_range`)
+    /// and folded into `SubscriberLag::last_delivered_index` for every
+    /// subscriber it reaches, so an operator can tell how far behind a
+    /// given consumer is without inspecting channel internals.
+    #[derive(Debug, Clone, Copy)]
+    pub struct AppliedRange {
+        pub start: u64,
+        pub end: u64,
+    }
+
+    /// Lag visible to an operator for one subscriber: the last
+    /// `applied_index` it was handed, and `queued`, the number of `dispatch`
+    /// calls currently blocked sending to it. The channel's own queue depth
+    /// is read from its sender by `CmdSinkRegistry::lag`.
+    #[derive(Default)]
+    pub struct SubscriberLag {
+        queued: AtomicUsize,
+        last_delivered_index: AtomicU64,
+    }
+
+    impl SubscriberLag {
+        pub fn queued(&self) -> usize {
+            self.queued.load(Ordering::Relaxed)
+        }
+
+        pub fn last_delivered_index(&self) -> u64 {
+            self.last_delivered_index.load(Ordering::Relaxed)
+        }
+    }
+
+    struct Subscriber {
+        sender: Sender<CmdBatch>,
+        lag: Arc<SubscriberLag>,
+    }
+
+    /// Registry of live `CmdBatch` subscribers. Shared (via `Clone`) by
+    /// whatever holds the owning `CmdObserver`, so every caller of
+    /// `dispatch` reaches the same set of subscribers.
+    #[derive(Clone, Default)]
+    pub struct CmdSinkRegistry {
+        subscribers: Arc<Mutex<HashMap<SubscriberId, Subscriber>>>,
+    }
+
+    impl CmdSinkRegistry {
+        pub fn new() -> CmdSinkRegistry {
+            CmdSinkRegistry::default()
+        }
+
+        /// Registers a new subscriber with a bounded channel of `capacity`
+        /// batches, returning its id (for `deregister`/`lag`) and the
+        /// receiving half.
+        pub fn register(&self, capacity: usize) -> (SubscriberId, Receiver<CmdBatch>) {
+            let (tx, rx) = channel::bounded(capacity);
+            let id = SubscriberId::new();
+            self.subscribers.lock().unwrap().insert(
+                id,
+                Subscriber {
+                    sender: tx,
+                    lag: Arc::new(SubscriberLag::default()),
+                },
+            );
+            (id, rx)
+        }
+
+        /// Drops `id`'s subscription. A no-op if it's already gone (e.g.
+        /// pruned by `dispatch` after its receiver disconnected).
+        pub fn deregister(&self, id: SubscriberId) {
+            self.subscribers.lock().unwrap().remove(&id);
+        }
+
+        /// This subscriber's current queue depth and last delivered
+        /// `applied_index`, or `None` if it isn't registered.
+        pub fn lag(&self, id: SubscriberId) -> Option<(usize, u64)> {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .get(&id)
+                .map(|s| (s.sender.len(), s.lag.last_delivered_index()))
+        }
+
+        /// Delivers `batch` - covering `applied_range` - to every live
+        /// subscriber. Blocks on a subscriber whose channel is currently
+        /// full, so a stalled consumer throttles the caller rather than
+        /// piling batches up in memory; a subscriber whose receiver has
+        /// disconnected is pruned instead of treated as an error.
+        ///
+        /// The registry lock is only held long enough to snapshot the
+        /// senders and, afterwards, to prune any that disconnected - never
+        /// across the blocking `send` itself. Holding it across `send` would
+        /// mean one stalled subscriber's full channel blocks `register`,
+        /// `deregister` and `lag` for every subscriber of every region
+        /// sharing this registry, not just the stalled one, and an operator
+        /// would have no way to inspect or remove the offender.
+        pub fn dispatch(&self, batch: CmdBatch, applied_range: AppliedRange) {
+            let snapshot: Vec<(SubscriberId, Sender<CmdBatch>, Arc<SubscriberLag>)> = {
+                let subscribers = self.subscribers.lock().unwrap();
+                if subscribers.is_empty() {
+                    return;
+                }
+                subscribers
+                    .iter()
+                    .map(|(id, sub)| (*id, sub.sender.clone(), sub.lag.clone()))
+                    .collect()
+            };
+
+            let mut disconnected = Vec::new();
+            for (id, sender, lag) in snapshot {
+                lag.queued.fetch_add(1, Ordering::Relaxed);
+                let sent = sender.send(batch.clone());
+                lag.queued.fetch_sub(1, Ordering::Relaxed);
+                match sent {
+                    Ok(()) => lag
+                        .last_delivered_index
+                        .store(applied_range.end, Ordering::Relaxed),
+                    Err(_) => disconnected.push(id),
+                }
+            }
+
+            if !disconnected.is_empty() {
+                let mut subscribers = self.subscribers.lock().unwrap();
+                for id in disconnected {
+                    subscribers.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+/// Block-level Merkle tree over an SST's raw bytes, checked by
+/// `check_sst_for_ingestion` against the `merkle_root` the SST's uploader
+/// recorded, so corruption picked up between upload and ingestion (a
+/// truncated object-storage fetch, a bit flip in transit) is caught before
+/// the bytes reach the engine rather than surfacing as a mismatch during
+/// compaction or read.
+///
+/// Leaf and internal-node hashes use SHA-256, so a mismatch against the
+/// `merkle_root` an uploader recorded actually means the bytes changed -
+/// unlike [`region_merkle`]'s weak `fnv64` hash (fine for its own
+/// accidental-collision-only use case), this tree is the one
+/// thing standing between a tampered SST and ingestion, so it needs to
+/// resist a deliberately crafted collision, not just a random one.
+mod sst_merkle {
+    use openssl::sha::sha256;
+
+    /// Leaves are hashed 64KiB at a time: large enough that a multi-GiB SST
+    /// still builds a tree with a manageable leaf count, small enough that
+    /// an inclusion proof only needs to fetch the one block it covers.
+    pub(crate) const BLOCK_SIZE: usize = 64 * 1024;
+
+    fn leaf_hashes(data: &[u8]) -> Vec<[u8; 32]> {
+        if data.is_empty() {
+            return vec![sha256(&[])];
+        }
+        data.chunks(BLOCK_SIZE).map(sha256).collect()
+    }
+
+    fn parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut buf = [0u8; 64];
+        buf[..32].copy_from_slice(left);
+        buf[32..].copy_from_slice(right);
+        sha256(&buf)
+    }
+
+    /// One level up the tree, duplicating the last node when `level` has an
+    /// odd count so every level pairs off cleanly.
+    fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+        let mut parents = Vec::with_capacity((level.len() + 1) / 2);
+        let mut i = 0;
+        while i < level.len() {
+            let left = &level[i];
+            let right = level.get(i + 1).unwrap_or(left);
+            parents.push(parent(left, right));
+            i += 2;
+        }
+        parents
+    }
+
+    /// The Merkle root over `data`'s 64KiB blocks, comparable against an
+    /// SST's recorded `merkle_root` once encoded with [`encode_root`].
+    pub(crate) fn root(data: &[u8]) -> [u8; 32] {
+        let mut level = leaf_hashes(data);
+        while level.len() > 1 {
+            level = next_level(&level);
+        }
+        level[0]
+    }
+
+    /// `root`'s on-the-wire form, matching the `Vec<u8>` an `SstMeta`'s
+    /// `merkle_root` field carries.
+    pub(crate) fn encode_root(root: [u8; 32]) -> Vec<u8> {
+        root.to_vec()
+    }
+
+    /// The sibling hash at each level from `leaf_index` up to the root, so a
+    /// verifier that only has one block of a large SST (the blocks
+    /// overlapping the region range it cares about) can confirm that block
+    /// against `merkle_root` without hashing the whole file.
+    pub(crate) struct InclusionProof {
+        pub(crate) leaf_index: usize,
+        pub(crate) siblings: Vec<[u8; 32]>,
+    }
+
+    pub(crate) fn prove(data: &[u8], leaf_index: usize) -> InclusionProof {
+        let mut level = leaf_hashes(data);
+        let mut idx = leaf_index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            let sibling_idx = if idx % 2 == 0 { idx + 1 } else { idx - 1 };
+            let sibling = *level.get(sibling_idx).unwrap_or(&level[idx]);
+            siblings.push(sibling);
+            level = next_level(&level);
+            idx /= 2;
+        }
+        InclusionProof {
+            leaf_index,
+            siblings,
+        }
+    }
+
+    /// Recomputes the root `proof` implies for `leaf_hash` and checks it
+    /// against `expected_root`.
+    pub(crate) fn verify_inclusion(
+        leaf_hash: [u8; 32],
+        proof: &InclusionProof,
+        expected_root: [u8; 32],
+    ) -> bool {
+        let mut hash = leaf_hash;
+        let mut idx = proof.leaf_index;
+        for sibling in &proof.siblings {
+            hash = if idx % 2 == 0 {
+                parent(&hash, sibling)
+            } else {
+                parent(sibling, &hash)
+            };
+            idx /= 2;
+        }
+        hash == expected_root
+    }
+}
+
+/// Append-only Merkle accumulator over one region's applied entries, so a
+/// downstream CDC or replication consumer can detect a missed or reordered
+/// `CmdBatch` instead of silently trusting the stream. Unlike
+/// [`region_merkle::RegionMerkle`], which digests current key/value content
+/// and is reset on split/merge, this tree only ever grows: each applied
+/// entry becomes one more leaf, in apply order, for as long as this store
+/// keeps the region.
+///
+/// The tree is kept as its right-hand frontier - one hash per level that's
+/// "closed" (covers a complete power-of-two run of leaves) - rather than
+/// the whole tree, the same structure a binary counter's carry chain
+/// produces: `frontier[level]` holds a hash exactly when bit `level` of the
+/// current leaf count is set. That keeps both `append` and `root` O(log n)
+/// instead of needing to rehash every leaf on every append.
+mod entry_accumulator {
+    use openssl::sha::sha256;
+
+    /// `sha256(region_id || index || term || cmd_hash)`, so an inclusion
+    /// proof actually certifies which entry occupies a leaf instead of just
+    /// certifying some 64-bit pre-image an adversary could search for.
+    fn leaf_hash(region_id: u64, index: u64, term: u64, cmd_hash: [u8; 32]) -> [u8; 32] {
+        let mut buf = Vec::with_capacity(8 + 8 + 8 + 32);
+        buf.extend_from_slice(&region_id.to_le_bytes());
+        buf.extend_from_slice(&index.to_le_bytes());
+        buf.extend_from_slice(&term.to_le_bytes());
+        buf.extend_from_slice(&cmd_hash);
+        sha256(&buf)
+    }
+
+    fn combine(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut buf = [0u8; 64];
+        buf[..32].copy_from_slice(&left);
+        buf[32..].copy_from_slice(&right);
+        sha256(&buf)
+    }
+
+    /// One leaf's inclusion proof: the hashes needed, each tagged with
+    /// which side it joins on, to fold the leaf's own hash up into the
+    /// root that was current as of [`EntryAccumulator::leaf_count`] leaves.
+    /// A later append doesn't invalidate an already-issued proof - it just
+    /// means the proof verifies against an earlier root than the
+    /// accumulator's current one.
+    pub struct InclusionProof {
+        pub leaf_index: u64,
+        /// `(sibling_hash, sibling_is_right)` at each step from the leaf up
+        /// to the root.
+        pub path: Vec<([u8; 32], bool)>,
+    }
+
+    /// Recomputes the root `proof` implies for `leaf_hash` and checks it
+    /// against `expected_root`.
+    pub fn verify_inclusion(
+        leaf_hash: [u8; 32],
+        proof: &InclusionProof,
+        expected_root: [u8; 32],
+    ) -> bool {
+        let mut hash = leaf_hash;
+        for &(sibling, is_right) in &proof.path {
+            hash = if is_right {
+                combine(hash, sibling)
+            } else {
+                combine(sibling, hash)
+            };
+        }
+        hash == expected_root
+    }
+
+    #[derive(Default)]
+    pub(crate) struct EntryAccumulator {
+        leaf_count: u64,
+        frontier: Vec<Option<[u8; 32]>>,
+        // Every leaf hash appended so far, needed to answer `prove` for any
+        // past leaf index. `append` itself never looks past the frontier,
+        // so this doesn't cost `append` anything beyond the push.
+        leaves: Vec<[u8; 32]>,
+    }
+
+    impl EntryAccumulator {
+        pub(crate) fn new() -> EntryAccumulator {
+            EntryAccumulator::default()
+        }
+
+        /// Appends one applied entry as a new leaf, returning its leaf
+        /// index and the accumulator's root immediately after.
+        pub(crate) fn append(
+            &mut self,
+            region_id: u64,
+            index: u64,
+            term: u64,
+            cmd_hash: [u8; 32],
+        ) -> (u64, [u8; 32]) {
+            let leaf_index = self.leaf_count;
+            let mut hash = leaf_hash(region_id, index, term, cmd_hash);
+            self.leaves.push(hash);
+            let mut level = 0;
+            loop {
+                if level == self.frontier.len() {
+                    self.frontier.push(None);
+                }
+                match self.frontier[level].take() {
+                    Some(left) => {
+                        hash = combine(left, hash);
+                        level += 1;
+                    }
+                    None => {
+                        self.frontier[level] = Some(hash);
+                        break;
+                    }
+                }
+            }
+            self.leaf_count += 1;
+            (leaf_index, self.root())
+        }
+
+        /// The accumulator's current root: the closed subtrees in
+        /// `frontier`, folded from the largest (earliest leaves) down to
+        /// the smallest (most recent leaves) - the same left-to-right order
+        /// the leaves themselves were appended in.
+        pub(crate) fn root(&self) -> [u8; 32] {
+            let mut acc: Option<[u8; 32]> = None;
+            for level in self.frontier.iter().rev() {
+                if let Some(h) = level {
+                    acc = Some(match acc {
+                        Some(a) => combine(a, *h),
+                        None => *h,
+                    });
+                }
+            }
+            acc.unwrap_or([0u8; 32])
+        }
+
+        /// The closed subtrees ("peaks") that currently partition
+        /// `0..leaf_count`, as `(level, size, start)`, ordered left to right
+        /// (largest/earliest first) to match `root`'s fold order.
+        fn peaks(&self) -> Vec<(usize, u64, u64)> {
+            let mut peaks = Vec::new();
+            let mut offset = 0u64;
+            for level in (0..self.frontier.len()).rev() {
+                if self.frontier[level].is_some() {
+                    let size = 1u64 << level;
+                    peaks.push((level, size, offset));
+                    offset += size;
+                }
+            }
+            peaks
+        }
+
+        /// An inclusion proof for `leaf_index`, valid against `self.root()`
+        /// as of this call. Panics if `leaf_index` hasn't been appended
+        /// yet.
+        pub(crate) fn prove(&self, leaf_index: u64) -> InclusionProof {
+            assert!(leaf_index < self.leaf_count, "leaf index out of range");
+            let peaks = self.peaks();
+            let peak_pos = peaks
+                .iter()
+                .position(|&(_, size, start)| leaf_index >= start && leaf_index < start + size)
+                .unwrap();
+            let (_, size, start) = peaks[peak_pos];
+
+            // The path within this leaf's own peak: a perfect binary tree
+            // over `leaves[start..start+size]`, so no odd-count
+            // duplication is needed at any level.
+            let mut path = Vec::new();
+            let mut layer = self.leaves[start as usize..(start + size) as usize].to_vec();
+            let mut idx = (leaf_index - start) as usize;
+            while layer.len() > 1 {
+                let (sibling_idx, is_right) = if idx % 2 == 0 {
+                    (idx + 1, true)
+                } else {
+                    (idx - 1, false)
+                };
+                path.push((layer[sibling_idx], is_right));
+                let mut next = Vec::with_capacity(layer.len() / 2);
+                let mut i = 0;
+                while i < layer.len() {
+                    next.push(combine(layer[i], layer[i + 1]));
+                    i += 2;
+                }
+                layer = next;
+                idx /= 2;
+            }
+
+            // Bag in the peaks to the left of this one as a single combined
+            // sibling (they fold together before ever meeting this peak),
+            // then the peaks to the right, one at a time, in the same order
+            // `root` combines them.
+            if peak_pos > 0 {
+                let mut acc: Option<[u8; 32]> = None;
+                for &(level, _, _) in &peaks[..peak_pos] {
+                    let h = self.frontier[level].unwrap();
+                    acc = Some(match acc {
+                        Some(a) => combine(a, h),
+                        None => h,
+                    });
+                }
+                path.push((acc.unwrap(), false));
+            }
+            for &(level, _, _) in &peaks[peak_pos + 1..] {
+                path.push((self.frontier[level].unwrap(), true));
+            }
+
+            InclusionProof { leaf_index, path }
+        }
+    }
+}
+
+/// Registry of [`entry_accumulator::EntryAccumulator`], keyed by region id
+/// and shared across every apply poller in the store (mirrors
+/// [`RegionMerkleManager`]), so a CDC or replication consumer can query the
+/// current root or request an inclusion proof for any region regardless of
+/// which poller is applying it.
+#[derive(Clone, Default)]
+pub struct EntryAccumulatorManager {
+    regions: Arc<Mutex<HashMap<u64, Arc<Mutex<entry_accumulator::EntryAccumulator>>>>>,
+}
+
+impl EntryAccumulatorManager {
+    pub fn new() -> EntryAccumulatorManager {
+        EntryAccumulatorManager::default()
+    }
+
+    fn get_or_insert(&self, region_id: u64) -> Arc<Mutex<entry_accumulator::EntryAccumulator>> {
+        self.regions
+            .lock()
+            .unwrap()
+            .entry(region_id)
+            .or_insert_with(|| Arc::new(Mutex::new(entry_accumulator::EntryAccumulator::new())))
+            .clone()
+    }
+
+    /// Appends one applied entry for `region_id`, returning its leaf index
+    /// and the accumulator's root immediately after.
+    pub fn append(
+        &self,
+        region_id: u64,
+        index: u64,
+        term: u64,
+        cmd_hash: [u8; 32],
+    ) -> (u64, [u8; 32]) {
+        let tree = self.get_or_insert(region_id);
+        let mut tree = tree.lock().unwrap();
+        tree.append(region_id, index, term, cmd_hash)
+    }
+
+    /// `region_id`'s current accumulator root, or `None` if this store has
+    /// never applied an entry for it.
+    pub fn root(&self, region_id: u64) -> Option<[u8; 32]> {
+        let tree = self.regions.lock().unwrap().get(&region_id)?.clone();
+        Some(tree.lock().unwrap().root())
+    }
+
+    /// An inclusion proof for `leaf_index` within `region_id`'s accumulator,
+    /// or `None` if the region is unknown to this store.
+    pub fn prove(
+        &self,
+        region_id: u64,
+        leaf_index: u64,
+    ) -> Option<entry_accumulator::InclusionProof> {
+        let tree = self.regions.lock().unwrap().get(&region_id)?.clone();
+        Some(tree.lock().unwrap().prove(leaf_index))
+    }
 }
 
 #[cfg(test)]
@@ -3330,6 +6373,47 @@ mod tests {
         notify2.send(()).unwrap();
     }
 
+    /// A small deterministic PRNG (xorshift64star), used below to generate
+    /// reproducible message-delivery permutations without pulling in the
+    /// `rand` crate: a failing seed can be reported and replayed exactly.
+    struct SimRng(u64);
+
+    impl SimRng {
+        fn new(seed: u64) -> SimRng {
+            SimRng(seed | 1)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let mut x = self.0;
+            x ^= x >> 12;
+            x ^= x << 25;
+            x ^= x >> 27;
+            self.0 = x;
+            x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        // Fisher-Yates, deterministic for a given seed.
+        fn shuffle<T>(&mut self, items: &mut [T]) {
+            for i in (1..items.len()).rev() {
+                let j = (self.next_u64() as usize) % (i + 1);
+                items.swap(i, j);
+            }
+        }
+    }
+
+    /// Dispatches `(region_id, msg)` pairs to their respective apply FSMs in
+    /// the order a seeded shuffle picks rather than the order they're
+    /// passed in, so a test can enumerate cross-region delivery orderings
+    /// (e.g. one region's `Msg::LogsUpToDate` racing another's `Msg::Apply`)
+    /// and assert the outcome is independent of arrival order, reproducibly
+    /// from `seed`.
+    fn run_permuted(router: &ApplyRouter, mut msgs: Vec<(u64, Msg)>, seed: u64) {
+        SimRng::new(seed).shuffle(&mut msgs);
+        for (region_id, msg) in msgs {
+            router.schedule_task(region_id, msg);
+        }
+    }
+
     fn fetch_apply_res(receiver: &::std::sync::mpsc::Receiver<PeerMsg<RocksEngine>>) -> ApplyRes {
         match receiver.recv_timeout(Duration::from_secs(3)) {
             Ok(PeerMsg::ApplyRes { res, .. }) => match res {
@@ -3355,6 +6439,12 @@ mod tests {
             coprocessor_host: CoprocessorHost::default(),
             importer,
             sender,
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
             _phantom: PhantomData,
             engine: engine.clone(),
             router: router.clone(),
@@ -3457,7 +6547,9 @@ mod tests {
             .is_none());
         // Make sure Apply and Snapshot are in the same batch.
         let (tx, _) = mpsc::sync_channel(0);
-        let snap_task = GenSnapTask::new(2, 0, tx);
+        let snap_task = GenSnapTask::new(2, 1, 0, tx);
+        let cached_region_info = CachedRegionInfoManager::new();
+        let snapshot_chunks = snapshot_chunking::SnapshotChunkRegistry::new();
         let cb: SnapshotCallback<RocksEngine> =
             Box::new(move |snap_ret, _region, apply_state, applied_index_term| {
                 if let Ok(snap) = snap_ret {
@@ -3466,6 +6558,8 @@ mod tests {
                         applied_index_term,
                         apply_state.clone(),
                         &region_scheduler,
+                        &cached_region_info,
+                        &snapshot_chunks,
                     );
                 }
             });
@@ -3686,7 +6780,9 @@ mod tests {
         post_admin_count: Arc<AtomicUsize>,
         post_query_count: Arc<AtomicUsize>,
         cmd_batches: RefCell<Vec<CmdBatch>>,
-        cmd_sink: Option<Arc<Mutex<Sender<CmdBatch>>>>,
+        // Fan-out to every independent downstream consumer currently
+        // subscribed - see `cmd_sink::CmdSinkRegistry`.
+        cmd_sink: cmd_sink::CmdSinkRegistry,
     }
 
     impl Coprocessor for ApplyObserver {}
@@ -3720,9 +6816,9 @@ mod tests {
             if !self.cmd_batches.borrow().is_empty() {
                 let batches = self.cmd_batches.replace(Vec::default());
                 for b in batches {
-                    if let Some(sink) = self.cmd_sink.as_ref() {
-                        sink.lock().unwrap().send(b).unwrap();
-                    }
+                    let (start, end) = b.applied_index_range();
+                    self.cmd_sink
+                        .dispatch(b, cmd_sink::AppliedRange { start, end });
                 }
             }
         }
@@ -3745,6 +6841,12 @@ mod tests {
             tag: "test-store".to_owned(),
             cfg,
             sender,
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
             _phantom: PhantomData,
             coprocessor_host: host,
             importer: importer.clone(),
@@ -3774,6 +6876,11 @@ mod tests {
         let resp = capture_rx.recv_timeout(Duration::from_secs(3)).unwrap();
         assert!(!resp.get_header().has_error(), "{:?}", resp);
         assert_eq!(resp.get_responses().len(), 3);
+        // The per-command apply detail recorded in `ApplyCallback` must make
+        // it onto the response the caller gets back, not just into a log line.
+        let write_detail = resp.get_exec_details_v2().get_write_detail();
+        assert!(write_detail.get_apply_write_wal_nanos() > 0
+            || write_detail.get_apply_write_memtable_nanos() > 0);
         let dk_k1 = keys::data_key(b"k1");
         let dk_k2 = keys::data_key(b"k2");
         let dk_k3 = keys::data_key(b"k3");
@@ -3982,14 +7089,89 @@ mod tests {
         system.shutdown();
     }
 
+    #[test]
+    fn test_deterministic_apply_ordering() {
+        // Two unrelated regions' `Apply` batches should reach the same
+        // final state no matter which is delivered to the router first -
+        // this is the cross-region independence the real poller relies on
+        // when e.g. one region's `Msg::LogsUpToDate` races another's
+        // `Msg::Apply`. Enumerate a handful of seeds so a regression is
+        // reproducible rather than depending on real thread-scheduler
+        // timing.
+        for seed in 0..8 {
+            let (_path, engine) = create_tmp_engine("test-sim");
+            let (_import_dir, importer) = create_tmp_importer("test-sim");
+            let (tx, rx) = mpsc::channel();
+            let sender = Notifier::Sender(tx);
+            let cfg = Arc::new(VersionTrack::new(Config::default()));
+            let (router, mut system) = create_apply_batch_system(&cfg.value());
+            let builder = super::Builder::<RocksWriteBatch> {
+                tag: "test-store".to_owned(),
+                cfg,
+                sender,
+                chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+                cached_region_info: CachedRegionInfoManager::new(),
+                ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+                region_merkle: RegionMerkleManager::new(),
+                snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+                entry_log: EntryAccumulatorManager::new(),
+                _phantom: PhantomData,
+                coprocessor_host: CoprocessorHost::default(),
+                importer,
+                engine: engine.clone(),
+                router: router.clone(),
+            };
+            system.spawn(format!("test-sim-{}", seed), builder);
+
+            for region_id in 1..=2 {
+                let mut reg = Registration::default();
+                reg.id = region_id;
+                reg.region.set_id(region_id);
+                reg.region.mut_peers().push(new_peer(2, region_id));
+                reg.region.mut_region_epoch().set_version(3);
+                router.schedule_task(region_id, Msg::Registration(reg));
+            }
+
+            let put_k1 = EntryBuilder::new(1, 1)
+                .put(b"k1", b"v1")
+                .epoch(0, 3)
+                .build();
+            let put_k2 = EntryBuilder::new(1, 1)
+                .put(b"k2", b"v2")
+                .epoch(0, 3)
+                .build();
+            let msgs = vec![
+                (1, Msg::apply(Apply::new(1, 1, vec![put_k1], 0, 1, 1))),
+                (2, Msg::apply(Apply::new(2, 1, vec![put_k2], 0, 1, 1))),
+            ];
+            run_permuted(&router, msgs, seed);
+
+            let mut seen = 0;
+            while seen < 2 {
+                let apply_res = fetch_apply_res(&rx);
+                assert_eq!(apply_res.apply_state.get_applied_index(), 1);
+                seen += 1;
+            }
+            assert_eq!(
+                engine.get_value(&keys::data_key(b"k1")).unwrap().unwrap(),
+                b"v1"
+            );
+            assert_eq!(
+                engine.get_value(&keys::data_key(b"k2")).unwrap().unwrap(),
+                b"v2"
+            );
+
+            system.shutdown();
+        }
+    }
+
     #[test]
     fn test_cmd_observer() {
         let (_path, engine) = create_tmp_engine("test-delegate");
         let (_import_dir, importer) = create_tmp_importer("test-delegate");
         let mut host = CoprocessorHost::default();
         let mut obs = ApplyObserver::default();
-        let (sink, cmdbatch_rx) = mpsc::channel();
-        obs.cmd_sink = Some(Arc::new(Mutex::new(sink)));
+        let (_cmd_sub, cmdbatch_rx) = obs.cmd_sink.register(16);
         host.registry
             .register_cmd_observer(1, BoxCmdObserver::new(obs));
 
@@ -4005,6 +7187,12 @@ mod tests {
             coprocessor_host: host,
             importer,
             engine,
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
             _phantom: PhantomData,
             router: router.clone(),
         };
@@ -4136,48 +7324,166 @@ mod tests {
         system.shutdown();
     }
 
+    #[test]
+    fn test_multiple_cmd_observers() {
+        let (_path, engine) = create_tmp_engine("test-delegate");
+        let (_import_dir, importer) = create_tmp_importer("test-delegate");
+        let mut host = CoprocessorHost::default();
+        let mut obs = ApplyObserver::default();
+        let (_cmd_sub, cmdbatch_rx) = obs.cmd_sink.register(16);
+        host.registry
+            .register_cmd_observer(1, BoxCmdObserver::new(obs));
+
+        let (tx, rx) = mpsc::channel();
+        let sender = Notifier::Sender(tx);
+        let cfg = Config::default();
+        let (router, mut system) = create_apply_batch_system(&cfg);
+        let _phantom = engine.write_batch();
+        let builder = super::Builder::<RocksWriteBatch> {
+            tag: "test-store".to_owned(),
+            cfg: Arc::new(VersionTrack::new(cfg)),
+            sender,
+            coprocessor_host: host,
+            importer,
+            engine,
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
+            _phantom: PhantomData,
+            router: router.clone(),
+        };
+        system.spawn("test-handle-raft".to_owned(), builder);
+
+        let mut reg = Registration::default();
+        reg.id = 3;
+        reg.region.set_id(1);
+        reg.region.mut_peers().push(new_peer(2, 3));
+        reg.region.set_end_key(b"k5".to_vec());
+        reg.region.mut_region_epoch().set_conf_ver(1);
+        reg.region.mut_region_epoch().set_version(3);
+        let region_epoch = reg.region.get_region_epoch().clone();
+        router.schedule_task(1, Msg::Registration(reg));
+
+        // Register two observers (e.g. CDC and resolved-ts) on the same region.
+        let enabled_a = Arc::new(AtomicBool::new(true));
+        let observe_id_a = ObserveID::new();
+        router.schedule_task(
+            1,
+            Msg::Change {
+                region_epoch: region_epoch.clone(),
+                cmd: ChangeCmd::RegisterObserver {
+                    observe_id: observe_id_a,
+                    region_id: 1,
+                    enabled: enabled_a.clone(),
+                },
+                cb: Callback::Read(Box::new(|resp: ReadResponse<_>| {
+                    assert!(!resp.response.get_header().has_error());
+                })),
+            },
+        );
+        let enabled_b = Arc::new(AtomicBool::new(true));
+        let observe_id_b = ObserveID::new();
+        router.schedule_task(
+            1,
+            Msg::Change {
+                region_epoch: region_epoch.clone(),
+                cmd: ChangeCmd::RegisterObserver {
+                    observe_id: observe_id_b,
+                    region_id: 1,
+                    enabled: enabled_b.clone(),
+                },
+                cb: Callback::Read(Box::new(|resp: ReadResponse<_>| {
+                    assert!(!resp.response.get_header().has_error());
+                })),
+            },
+        );
+
+        let put_entry = EntryBuilder::new(1, 2)
+            .put(b"k1", b"v1")
+            .epoch(1, 3)
+            .build();
+        router.schedule_task(1, Msg::apply(Apply::new(1, 2, vec![put_entry], 0, 1, 1)));
+        fetch_apply_res(&rx);
+        // Both observers must receive their own cmd batch for the same command.
+        cmdbatch_rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        cmdbatch_rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        cmdbatch_rx
+            .recv_timeout(Duration::from_millis(100))
+            .unwrap_err();
+
+        // Deregister observer A; only B should keep receiving commands.
+        router.schedule_task(
+            1,
+            Msg::Change {
+                region_epoch,
+                cmd: ChangeCmd::DeregisterObserver {
+                    observe_id: observe_id_a,
+                    region_id: 1,
+                },
+                cb: Callback::Read(Box::new(|resp: ReadResponse<_>| {
+                    assert!(!resp.response.get_header().has_error());
+                })),
+            },
+        );
+        let put_entry = EntryBuilder::new(2, 2)
+            .put(b"k2", b"v2")
+            .epoch(1, 3)
+            .build();
+        router.schedule_task(1, Msg::apply(Apply::new(1, 2, vec![put_entry], 1, 2, 2)));
+        cmdbatch_rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        cmdbatch_rx
+            .recv_timeout(Duration::from_millis(100))
+            .unwrap_err();
+
+        system.shutdown();
+    }
+
     #[test]
     fn test_check_sst_for_ingestion() {
         let mut sst = SstMeta::default();
         let mut region = Region::default();
+        let (_import_dir, importer) = create_tmp_importer("test-check-sst-for-ingestion");
 
         // Check uuid and cf name
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.set_uuid(Uuid::new_v4().as_bytes().to_vec());
         sst.set_cf_name(CF_DEFAULT.to_owned());
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
         sst.set_cf_name("test".to_owned());
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.set_cf_name(CF_WRITE.to_owned());
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
 
         // Check region id
         region.set_id(1);
         sst.set_region_id(2);
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.set_region_id(1);
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
 
         // Check region epoch
         region.mut_region_epoch().set_conf_ver(1);
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.mut_region_epoch().set_conf_ver(1);
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
         region.mut_region_epoch().set_version(1);
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.mut_region_epoch().set_version(1);
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
 
         // Check region range
         region.set_start_key(vec![2]);
         region.set_end_key(vec![8]);
         sst.mut_range().set_start(vec![1]);
         sst.mut_range().set_end(vec![8]);
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.mut_range().set_start(vec![2]);
-        assert!(check_sst_for_ingestion(&sst, &region).is_err());
+        assert!(check_sst_for_ingestion(&sst, &region, &importer).is_err());
         sst.mut_range().set_end(vec![7]);
-        check_sst_for_ingestion(&sst, &region).unwrap();
+        check_sst_for_ingestion(&sst, &region, &importer).unwrap();
     }
 
     fn new_split_req(key: &[u8], id: u64, children: Vec<u64>) -> SplitRequest {
@@ -4255,8 +7561,7 @@ mod tests {
         let sender = Notifier::Sender(tx);
         let mut host = CoprocessorHost::default();
         let mut obs = ApplyObserver::default();
-        let (sink, cmdbatch_rx) = mpsc::channel();
-        obs.cmd_sink = Some(Arc::new(Mutex::new(sink)));
+        let (_cmd_sub, cmdbatch_rx) = obs.cmd_sink.register(16);
         host.registry
             .register_cmd_observer(1, BoxCmdObserver::new(obs));
         let cfg = Arc::new(VersionTrack::new(Config::default()));
@@ -4266,6 +7571,12 @@ mod tests {
             cfg,
             sender,
             importer,
+            chunk_store: Arc::new(content_chunking::ChunkStore::default()),
+            cached_region_info: CachedRegionInfoManager::new(),
+            ingest_quarantine: Arc::new(ingest_quarantine::IngestQuarantine::new()),
+            region_merkle: RegionMerkleManager::new(),
+            snapshot_chunks: snapshot_chunking::SnapshotChunkRegistry::new(),
+            entry_log: EntryAccumulatorManager::new(),
             _phantom: PhantomData,
             coprocessor_host: host,
             engine: engine.clone(),
@@ -4472,4 +7783,36 @@ mod tests {
         });
         res.unwrap_err();
     }
+
+    #[test]
+    fn test_content_chunking_dedup() {
+        // Same bytes ingested under two different SST uuids must chunk
+        // identically and share storage - the dedup this module exists for.
+        // 200KiB is comfortably past MAX_CHUNK_SIZE, so this spans several
+        // chunks rather than exercising only the single-chunk tail case.
+        let data = vec![7u8; 200 * 1024];
+        let store = content_chunking::ChunkStore::default();
+        let written_first = store.put_sst(b"sst-a".to_vec(), &data);
+        assert!(written_first > 0);
+        let chunks_after_first = store.len();
+
+        let written_second = store.put_sst(b"sst-b".to_vec(), &data);
+        assert_eq!(written_second, 0, "identical content must not be re-stored");
+        assert_eq!(store.len(), chunks_after_first);
+
+        // Different content must not collide onto the same chunk hashes.
+        let other = vec![9u8; data.len()];
+        let written_third = store.put_sst(b"sst-c".to_vec(), &other);
+        assert!(written_third > 0);
+        assert!(store.verify_sst(b"sst-a", &data));
+        assert!(store.verify_sst(b"sst-c", &other));
+        assert!(!store.verify_sst(b"sst-a", &other));
+
+        // Releasing one referencing SST must not drop chunks the other still
+        // references; only dropping every referencing SST frees them.
+        store.release_sst(b"sst-a");
+        assert_eq!(store.len(), chunks_after_first + written_third);
+        store.release_sst(b"sst-b");
+        assert_eq!(store.len(), written_third);
+    }
 }